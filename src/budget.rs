@@ -0,0 +1,116 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Limits on the number of bytes that may be allocated for segments, either by
+//! a single array or by a group of arrays sharing the same budget.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+///
+/// Handle to a limit on the total size of the segments allocated by the arrays
+/// to which it is attached with [`SegmentArray::set_memory_budget`]. Clones of
+/// the handle share the same budget.
+///
+/// Allocations that would exceed the budget fail with
+/// [`TryReserveError::BudgetExceeded`] from the fallible methods such as
+/// [`SegmentArray::try_push`], while the infallible methods will panic.
+///
+/// ```
+/// # use segment_array::{MemoryBudget, SegmentArray, TryReserveError};
+/// let budget = MemoryBudget::new(1024);
+/// let mut first: SegmentArray<u32> = SegmentArray::new();
+/// let mut second: SegmentArray<u32> = SegmentArray::new();
+/// first.set_memory_budget(budget.clone()).unwrap();
+/// second.set_memory_budget(budget.clone()).unwrap();
+/// assert!(first.try_reserve(100).is_ok());
+/// assert_eq!(budget.used(), 768);
+/// assert!(matches!(
+///     second.try_reserve(100),
+///     Err(TryReserveError::BudgetExceeded { .. })
+/// ));
+/// ```
+///
+/// [`SegmentArray::set_memory_budget`]: crate::SegmentArray::set_memory_budget
+/// [`SegmentArray::try_push`]: crate::SegmentArray::try_push
+/// [`TryReserveError::BudgetExceeded`]: crate::TryReserveError::BudgetExceeded
+///
+#[derive(Clone, Debug)]
+pub struct MemoryBudget(Arc<BudgetState>);
+
+#[derive(Debug)]
+struct BudgetState {
+    // maximum number of bytes
+    limit: usize,
+    // number of bytes currently allocated
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// Return a budget that permits allocating at most the given number of
+    /// bytes.
+    pub fn new(limit: usize) -> Self {
+        Self(Arc::new(BudgetState {
+            limit,
+            used: AtomicUsize::new(0),
+        }))
+    }
+
+    /// Returns the maximum number of bytes.
+    pub fn limit(&self) -> usize {
+        self.0.limit
+    }
+
+    /// Returns the number of bytes currently allocated against the budget.
+    pub fn used(&self) -> usize {
+        self.0.used.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of bytes that may yet be allocated.
+    pub fn remaining(&self) -> usize {
+        self.0.limit.saturating_sub(self.used())
+    }
+
+    /// Returns true if both handles refer to the same budget.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Records the allocation of the given number of bytes, returning false
+    /// if that would exceed the budget.
+    pub(crate) fn try_acquire(&self, bytes: usize) -> bool {
+        self.0
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes)
+                    .filter(|total| *total <= self.0.limit)
+            })
+            .is_ok()
+    }
+
+    /// Records the deallocation of the given number of bytes.
+    pub(crate) fn release(&self, bytes: usize) {
+        self.0.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_release() {
+        let sut = MemoryBudget::new(100);
+        assert!(sut.try_acquire(60));
+        assert!(!sut.try_acquire(41));
+        assert!(sut.try_acquire(40));
+        assert_eq!(sut.remaining(), 0);
+        sut.release(60);
+        assert_eq!(sut.used(), 40);
+        assert_eq!(sut.remaining(), 60);
+        assert!(!sut.try_acquire(usize::MAX));
+        assert!(sut.ptr_eq(&sut.clone()));
+        assert!(!sut.ptr_eq(&MemoryBudget::new(100)));
+    }
+}
@@ -0,0 +1,742 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Operations specific to segment arrays of bytes, such as vectored I/O and
+//! splitting into records.
+
+use super::raw::{MAX_SEGMENT_COUNT, capacity_for_segment_count, locate, slots_in_segment};
+use super::storage::Heap;
+use super::{SegmentArray, SegmentStorage};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, IoSlice, Read, Write};
+use std::iter::FusedIterator;
+
+// Largest buffer given to a reader in a single call; the buffer must be zeroed
+// before the first read into it, so this bounds the wasted effort when the
+// reader has little data to offer.
+const READ_CHUNK_SIZE: usize = 1 << 20;
+
+impl<S: SegmentStorage> SegmentArray<u8, S> {
+    /// Returns the occupied portion of each segment as an `IoSlice`, of
+    /// which there will be at most 26.
+    pub fn as_io_slices(&self) -> impl Iterator<Item = IoSlice<'_>> {
+        self.range_slices(0, self.count).map(IoSlice::new)
+    }
+
+    /// Writes the entire contents of the array to the writer, using vectored
+    /// writes such that all of the segments may be written in a single call.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the writer other than `ErrorKind::Interrupted`,
+    /// and `ErrorKind::WriteZero` if the writer stops accepting data.
+    pub fn write_all_vectored_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let mut slices = [IoSlice::new(&[]); MAX_SEGMENT_COUNT];
+        let mut len = 0;
+        for slice in self.as_io_slices() {
+            slices[len] = slice;
+            len += 1;
+        }
+        let mut bufs = &mut slices[..len];
+        while !bufs.is_empty() {
+            match writer.write_vectored(bufs) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole buffer",
+                    ));
+                }
+                Ok(written) => IoSlice::advance_slices(&mut bufs, written),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads up to `max_bytes` from the reader directly into the spare
+    /// capacity of the array, allocating segments as needed, until either
+    /// `max_bytes` have been read or the reader reaches end-of-file. Returns
+    /// the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the reader other than `ErrorKind::Interrupted`,
+    /// in which case the bytes read prior to the error remain in the array.
+    /// Likewise returns an error of kind `ErrorKind::OutOfMemory`, wrapping a
+    /// [`TryReserveError`](crate::TryReserveError), if a segment cannot be
+    /// allocated, such as when it would exceed the memory budget.
+    pub fn read_from<R: Read + ?Sized>(
+        &mut self,
+        reader: &mut R,
+        max_bytes: usize,
+    ) -> io::Result<usize> {
+        let mut total = 0;
+        // end of the zero-filled portion of the spare capacity
+        let mut zeroed = 0;
+        // true if a segment was allocated that has yet to receive any bytes
+        let mut allocated = false;
+        while total < max_bytes {
+            if self.count >= self.capacity() {
+                self.try_allocate_segment(false)
+                    .map_err(|err| io::Error::new(io::ErrorKind::OutOfMemory, err))?;
+                allocated = true;
+            }
+            let (segment, slot) = locate(self.count);
+            let len = (slots_in_segment(segment) - slot)
+                .min(max_bytes - total)
+                .min(READ_CHUNK_SIZE);
+            let result = self
+                .segments
+                .read_spare(self.count, len, &mut zeroed, reader);
+            match result {
+                Ok(0) => break,
+                Ok(read) => {
+                    self.count += read;
+                    total += read;
+                    allocated = false;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.release_empty_segment(allocated);
+                    return Err(err);
+                }
+            }
+        }
+        self.release_empty_segment(allocated);
+        Ok(total)
+    }
+
+    /// Deallocates the last segment if it was allocated by `read_from()` but
+    /// received no bytes, such as when the reader was already at the end.
+    fn release_empty_segment(&mut self, allocated: bool) {
+        if allocated && self.count <= capacity_for_segment_count(self.segments.used() - 1) {
+            self.deallocate_segment();
+        }
+    }
+
+    /// Returns an iterator over the records separated by the delimiter, in
+    /// the same manner as `slice::split()`. Records that reside within a
+    /// single segment are borrowed, while those that straddle a segment
+    /// boundary are copied.
+    pub fn split_on(&self, delim: u8) -> Split<'_, S> {
+        Split {
+            array: self,
+            delim,
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Returns an iterator over the lines of the array, in the same manner as
+    /// `str::lines()`: lines end with either `\n` or `\r\n`, which are not
+    /// included in the line, and the final line ending is optional. Lines are
+    /// borrowed or copied as with [`Self::split_on`].
+    pub fn lines(&self) -> Lines<'_, S> {
+        Lines {
+            split: self.split_on(b'\n'),
+        }
+    }
+
+    /// Validates that the entire array is UTF-8 and returns an iterator over
+    /// the contents of each segment as string slices, without copying.
+    ///
+    /// A character that straddles a segment boundary cannot be borrowed and
+    /// is instead yielded as a separate, owned string of at most 4 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the array is not valid UTF-8, with the offset of
+    /// the invalid sequence.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn as_str_chunks(&self) -> Result<StrChunks<'_, S>, Utf8Error> {
+        let mut position = 0;
+        while position < self.count {
+            let slice = self.range_slices(position, self.count).next().unwrap();
+            match std::str::from_utf8(slice) {
+                Ok(_) => position += slice.len(),
+                Err(err) if err.error_len().is_some() => {
+                    return Err(Utf8Error {
+                        valid_up_to: position + err.valid_up_to(),
+                        error_len: err.error_len(),
+                    });
+                }
+                Err(err) => {
+                    // the character continues into the next segment, if any
+                    let start = position + err.valid_up_to();
+                    let end = (start + utf8_char_width(slice[err.valid_up_to()])).min(self.count);
+                    if let Err(err) = std::str::from_utf8(&self.record(start, end)) {
+                        return Err(Utf8Error {
+                            valid_up_to: start,
+                            error_len: err.error_len(),
+                        });
+                    }
+                    position = end;
+                }
+            }
+        }
+        Ok(StrChunks {
+            array: self,
+            position: 0,
+            straddling: None,
+        })
+    }
+
+    /// Returns the offset of the first occurrence of the needle within the
+    /// array, in the manner of [`Self::find`], using the Boyer-Moore-Horspool
+    /// algorithm.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n / m) for typical inputs, and O(n * m) in the worst case, where n is
+    /// the number of elements in the array and m is the length of the needle.
+    pub fn find_bytes(&self, needle: &[u8]) -> Option<usize> {
+        let len = needle.len();
+        match len {
+            0 => return Some(0),
+            1 => return self.iter().position(|b| *b == needle[0]),
+            _ if len > self.count => return None,
+            _ => {}
+        }
+        // distance to shift the needle for each value of its last byte
+        let mut shifts = [len; 256];
+        for (offset, byte) in needle[..len - 1].iter().enumerate() {
+            shifts[*byte as usize] = len - 1 - offset;
+        }
+        let last = needle[len - 1];
+        let mut start = 0;
+        while start + len <= self.count {
+            let byte = self[start + len - 1];
+            if byte == last && self.matches_at(start, needle) {
+                return Some(start);
+            }
+            start += shifts[byte as usize];
+        }
+        None
+    }
+
+    /// Returns the bytes in the given range, borrowing them if they reside
+    /// within a single segment.
+    fn record(&self, start: usize, end: usize) -> Cow<'_, [u8]> {
+        let mut slices = self.range_slices(start, end);
+        match (slices.next(), slices.next()) {
+            (None, _) => Cow::Borrowed(&[]),
+            (Some(first), None) => Cow::Borrowed(first),
+            (Some(first), Some(second)) => {
+                let mut record = Vec::with_capacity(end - start);
+                record.extend_from_slice(first);
+                record.extend_from_slice(second);
+                for slice in slices {
+                    record.extend_from_slice(slice);
+                }
+                Cow::Owned(record)
+            }
+        }
+    }
+}
+
+// Converts bytes that were validated as UTF-8 when the iterator was created,
+// without checking them again unless built with `segment_array_safe`.
+#[cfg(not(segment_array_safe))]
+fn validated_str(bytes: &[u8]) -> &str {
+    unsafe { std::str::from_utf8_unchecked(bytes) }
+}
+
+#[cfg(segment_array_safe)]
+fn validated_str(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).expect("bytes were validated as UTF-8")
+}
+
+// Returns the length of the character that starts with the given byte, which
+// must be the first byte of a valid sequence.
+fn utf8_char_width(byte: u8) -> usize {
+    match byte {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    }
+}
+
+///
+/// Error returned by [`SegmentArray::as_str_chunks`] when the array is not
+/// valid UTF-8, equivalent to `std::str::Utf8Error` but with offsets that
+/// refer to the entire array.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Utf8Error {
+    valid_up_to: usize,
+    error_len: Option<usize>,
+}
+
+impl Utf8Error {
+    /// Returns the offset up to which the array is valid UTF-8.
+    pub fn valid_up_to(&self) -> usize {
+        self.valid_up_to
+    }
+
+    /// Returns the length of the invalid byte sequence, or `None` if the
+    /// array ends with an incomplete character.
+    pub fn error_len(&self) -> Option<usize> {
+        self.error_len
+    }
+}
+
+impl fmt::Display for Utf8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error_len {
+            Some(len) => write!(
+                f,
+                "invalid utf-8 sequence of {} bytes from index {}",
+                len, self.valid_up_to
+            ),
+            None => write!(
+                f,
+                "incomplete utf-8 byte sequence from index {}",
+                self.valid_up_to
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Utf8Error {}
+
+///
+/// Iterator over the contents of a byte array as strings, as returned by
+/// [`SegmentArray::as_str_chunks`].
+///
+pub struct StrChunks<'a, S: SegmentStorage = Heap> {
+    array: &'a SegmentArray<u8, S>,
+    // offset of the next byte, always at a character boundary
+    position: usize,
+    // character that straddles the end of the previous segment
+    straddling: Option<String>,
+}
+
+impl<'a, S: SegmentStorage> Iterator for StrChunks<'a, S> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(straddling) = self.straddling.take() {
+            return Some(Cow::Owned(straddling));
+        }
+        let count = self.array.count;
+        let slice = self.array.range_slices(self.position, count).next()?;
+        // find the start of a character that is incomplete in this segment
+        let mut cut = slice.len();
+        for (offset, byte) in slice.iter().enumerate().rev().take(3) {
+            if byte & 0xc0 != 0x80 {
+                if offset + utf8_char_width(*byte) > slice.len() {
+                    cut = offset;
+                }
+                break;
+            }
+        }
+        let start = self.position;
+        self.position += slice.len();
+        if cut < slice.len() {
+            let end = start + cut + utf8_char_width(slice[cut]);
+            let bytes = self.array.record(start + cut, end);
+            self.straddling = Some(validated_str(&bytes).to_owned());
+            self.position = end;
+        }
+        if cut > 0 {
+            Some(Cow::Borrowed(validated_str(&slice[..cut])))
+        } else {
+            self.straddling.take().map(Cow::Owned)
+        }
+    }
+}
+
+impl<S: SegmentStorage> FusedIterator for StrChunks<'_, S> {}
+
+///
+/// Iterator over the records of a byte array that are separated by a
+/// delimiter, as returned by [`SegmentArray::split_on`].
+///
+pub struct Split<'a, S: SegmentStorage = Heap> {
+    array: &'a SegmentArray<u8, S>,
+    delim: u8,
+    // offset of the start of the next record
+    position: usize,
+    // true once the last record has been yielded
+    finished: bool,
+}
+
+impl<'a, S: SegmentStorage> Iterator for Split<'a, S> {
+    type Item = Cow<'a, [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let start = self.position;
+        let mut end = start;
+        for slice in self.array.range_slices(start, self.array.count) {
+            if let Some(offset) = slice.iter().position(|b| *b == self.delim) {
+                self.position = end + offset + 1;
+                return Some(self.array.record(start, end + offset));
+            }
+            end += slice.len();
+        }
+        self.finished = true;
+        Some(self.array.record(start, end))
+    }
+}
+
+impl<S: SegmentStorage> FusedIterator for Split<'_, S> {}
+
+///
+/// Iterator over the lines of a byte array, as returned by
+/// [`SegmentArray::lines`].
+///
+pub struct Lines<'a, S: SegmentStorage = Heap> {
+    split: Split<'a, S>,
+}
+
+impl<'a, S: SegmentStorage> Iterator for Lines<'a, S> {
+    type Item = Cow<'a, [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.split.next()?;
+        // a final line ending does not start another line
+        if self.split.finished && line.is_empty() {
+            return None;
+        }
+        // only a line ending of `\r\n` is stripped, not a lone `\r` at the
+        // end of the final line
+        if self.split.finished {
+            return Some(line);
+        }
+        Some(match line {
+            Cow::Borrowed(line) => Cow::Borrowed(line.strip_suffix(b"\r").unwrap_or(line)),
+            Cow::Owned(mut line) => {
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                Cow::Owned(line)
+            }
+        })
+    }
+}
+
+impl<S: SegmentStorage> FusedIterator for Lines<'_, S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryBudget, TryReserveError};
+
+    // Writer that accepts only a few bytes from the first buffer per call.
+    struct Trickle {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls == 2 {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            let len = buf.len().min(100);
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Writer that refuses to accept any data.
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Reader that produces a fixed number of bytes in small pieces, after
+    // being interrupted once.
+    struct Drip {
+        remaining: usize,
+        calls: usize,
+    }
+
+    impl Read for Drip {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls == 2 {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            assert!(buf.iter().all(|b| *b == 0));
+            let len = buf.len().min(self.remaining).min(30);
+            for (idx, byte) in buf[..len].iter_mut().enumerate() {
+                *byte = (self.remaining - idx) as u8;
+            }
+            self.remaining -= len;
+            Ok(len)
+        }
+    }
+
+    // Reader that always fails.
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+    }
+
+    #[test]
+    fn test_read_from() {
+        let input: Vec<u8> = (0..5000).map(|v| (v * 7) as u8).collect();
+        let mut sut: SegmentArray<u8> = SegmentArray::new();
+        assert_eq!(sut.read_from(&mut input.as_slice(), 0).unwrap(), 0);
+        assert_eq!(sut.read_from(&mut input.as_slice(), 100).unwrap(), 100);
+        assert_eq!(sut.len(), 100);
+        let read = sut.read_from(&mut &input[100..], usize::MAX).unwrap();
+        assert_eq!(read, 4900);
+        assert_eq!(sut.to_vec_copy(), input);
+        // reads append to existing content
+        assert_eq!(sut.read_from(&mut &input[..10], 1000).unwrap(), 10);
+        assert_eq!(sut.len(), 5010);
+        assert_eq!(sut[5009], input[9]);
+        // no segment is left allocated by a reader that is at the end
+        let mut sut: SegmentArray<u8> = SegmentArray::new();
+        assert_eq!(sut.read_from(&mut io::empty(), 100).unwrap(), 0);
+        assert_eq!(sut.capacity(), 0);
+        assert_eq!(sut.read_from(&mut &input[..64], 100).unwrap(), 64);
+        assert_eq!(sut.capacity(), 64);
+        let err = sut.read_from(&mut Broken, 10).unwrap_err();
+        assert_eq!(err.to_string(), "broken");
+        assert_eq!(sut.capacity(), 64);
+        // a segment that was already reserved is retained
+        sut.reserve(1);
+        assert_eq!(sut.read_from(&mut io::empty(), 100).unwrap(), 0);
+        assert_eq!(sut.capacity(), 192);
+    }
+
+    #[test]
+    fn test_read_from_budget() {
+        let budget = MemoryBudget::new(192);
+        let mut sut: SegmentArray<u8> = SegmentArray::new();
+        sut.set_memory_budget(budget.clone()).unwrap();
+        let input = [7u8; 1000];
+        let err = sut.read_from(&mut &input[..], 1000).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<TryReserveError>(),
+            Some(&TryReserveError::BudgetExceeded {
+                requested: 256,
+                remaining: 0
+            })
+        );
+        // the bytes that fit within the budget were read
+        assert_eq!(sut.len(), 192);
+        assert_eq!(budget.used(), 192);
+        assert!(sut.iter().all(|b| *b == 7));
+    }
+
+    // reading the poisoned slots is an error when using AddressSanitizer
+    #[cfg(all(feature = "poison", not(feature = "asan"), not(segment_array_safe)))]
+    #[test]
+    fn test_read_from_poison() {
+        let mut sut: SegmentArray<u8> = SegmentArray::new();
+        assert_eq!(sut.read_from(&mut &[1u8; 10][..], 20).unwrap(), 10);
+        // the zeroed slots that the reader did not fill are poisoned again
+        let read_slot =
+            |sut: &SegmentArray<u8>, index| unsafe { sut.segments.raw_slot(index).read() };
+        assert_eq!(read_slot(&sut, 10), 0xCD);
+        assert_eq!(read_slot(&sut, 19), 0xCD);
+        assert_eq!(sut.read_from(&mut &[2u8; 5][..], 20).unwrap(), 5);
+        assert_eq!(sut[14], 2);
+        assert_eq!(read_slot(&sut, 15), 0xCD);
+    }
+
+    #[test]
+    fn test_read_from_small_reads() {
+        let mut sut: SegmentArray<u8> = SegmentArray::new();
+        let mut drip = Drip {
+            remaining: 1000,
+            calls: 0,
+        };
+        assert_eq!(sut.read_from(&mut drip, 2000).unwrap(), 1000);
+        assert_eq!(sut.len(), 1000);
+        for (idx, byte) in sut.iter().enumerate() {
+            assert_eq!(*byte, (1000 - idx) as u8);
+        }
+        let err = sut.read_from(&mut Broken, 10).unwrap_err();
+        assert_eq!(err.to_string(), "broken");
+        assert_eq!(sut.len(), 1000);
+    }
+
+    #[test]
+    fn test_split_on() {
+        let input: Vec<u8> = (0..1000)
+            .map(|v| {
+                if v % 50 == 49 {
+                    b','
+                } else {
+                    b'a' + (v % 26) as u8
+                }
+            })
+            .collect();
+        let sut: SegmentArray<u8> = input.iter().copied().collect();
+        let records: Vec<Cow<[u8]>> = sut.split_on(b',').collect();
+        let expected: Vec<&[u8]> = input.split(|b| *b == b',').collect();
+        assert_eq!(records.len(), expected.len());
+        for (record, expected) in records.iter().zip(expected) {
+            assert_eq!(record.as_ref(), expected);
+        }
+        // records within a segment are borrowed, others are copied
+        assert!(matches!(records[0], Cow::Borrowed(_)));
+        assert!(matches!(records[1], Cow::Owned(_)));
+        assert!(matches!(records[2], Cow::Borrowed(_)));
+
+        let empty: SegmentArray<u8> = SegmentArray::new();
+        let records: Vec<Cow<[u8]>> = empty.split_on(b',').collect();
+        assert_eq!(records, vec![Cow::Borrowed(&[] as &[u8])]);
+    }
+
+    #[test]
+    fn test_lines() {
+        let text = "first\r\nsecond line that is somewhat long\n\nthird\r\nfourth line that crosses into the third segment of the array\r\n";
+        let sut: SegmentArray<u8> = text.bytes().collect();
+        let lines: Vec<Cow<[u8]>> = sut.lines().collect();
+        let expected: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), expected.len());
+        for (line, expected) in lines.iter().zip(expected) {
+            assert_eq!(line.as_ref(), expected.as_bytes());
+        }
+        let sut: SegmentArray<u8> = b"no line ending".iter().copied().collect();
+        let lines: Vec<Cow<[u8]>> = sut.lines().collect();
+        assert_eq!(lines, vec![Cow::Borrowed(b"no line ending" as &[u8])]);
+        let sut: SegmentArray<u8> = b"abc\r".iter().copied().collect();
+        let lines: Vec<Cow<[u8]>> = sut.lines().collect();
+        assert_eq!(lines, vec![Cow::Borrowed(b"abc\r" as &[u8])]);
+        assert_eq!("abc\r".lines().collect::<Vec<_>>(), ["abc\r"]);
+        let empty: SegmentArray<u8> = SegmentArray::new();
+        assert_eq!(empty.lines().count(), 0);
+    }
+
+    #[test]
+    fn test_as_str_chunks() {
+        // characters of 1 to 4 bytes in length will straddle the segments
+        let text: String = "aé€😀".chars().cycle().take(1000).collect();
+        let sut: SegmentArray<u8> = text.bytes().collect();
+        let chunks: Vec<Cow<str>> = sut.as_str_chunks().unwrap().collect();
+        assert_eq!(chunks.concat(), text);
+        assert!(chunks.iter().any(|c| matches!(c, Cow::Owned(_))));
+        for chunk in chunks.iter().filter(|c| matches!(c, Cow::Owned(_))) {
+            assert_eq!(chunk.chars().count(), 1);
+        }
+
+        let empty: SegmentArray<u8> = SegmentArray::new();
+        assert_eq!(empty.as_str_chunks().unwrap().count(), 0);
+        let ascii: SegmentArray<u8> = "hello".bytes().collect();
+        let chunks: Vec<Cow<str>> = ascii.as_str_chunks().unwrap().collect();
+        assert_eq!(chunks, vec![Cow::Borrowed("hello")]);
+    }
+
+    #[test]
+    fn test_as_str_chunks_invalid() {
+        // invalid byte within a segment
+        let mut sut: SegmentArray<u8> = "a".repeat(100).bytes().collect();
+        sut[80] = 0xff;
+        let err = sut.as_str_chunks().err().unwrap();
+        assert_eq!(err.valid_up_to(), 80);
+        assert_eq!(err.error_len(), Some(1));
+        assert_eq!(
+            err.to_string(),
+            "invalid utf-8 sequence of 1 bytes from index 80"
+        );
+
+        // invalid sequence that straddles a segment boundary
+        let mut sut: SegmentArray<u8> = "a".repeat(100).bytes().collect();
+        sut[63] = 0xe2;
+        sut[64] = 0x82;
+        let err = sut.as_str_chunks().err().unwrap();
+        assert_eq!(err.valid_up_to(), 63);
+        assert_eq!(err.error_len(), Some(2));
+
+        // incomplete character at the end of the array
+        let mut sut: SegmentArray<u8> = "a".repeat(64).bytes().collect();
+        sut.push(0xf0);
+        sut.push(0x9f);
+        let err = sut.as_str_chunks().err().unwrap();
+        assert_eq!(err.valid_up_to(), 64);
+        assert_eq!(err.error_len(), None);
+        sut[63] = 0xf0;
+        let err = sut.as_str_chunks().err().unwrap();
+        assert_eq!(err.valid_up_to(), 63);
+    }
+
+    #[test]
+    fn test_find_bytes() {
+        let text = "the quick brown fox jumps over the lazy dog ".repeat(20);
+        let sut: SegmentArray<u8> = text.bytes().collect();
+        for needle in [
+            "the",
+            "lazy dog the quick",
+            "fox",
+            "t",
+            "dog ",
+            "cat",
+            "zy dog the",
+        ] {
+            let expected = text.find(needle);
+            assert_eq!(sut.find_bytes(needle.as_bytes()), expected, "{needle}");
+            assert_eq!(sut.find(needle.as_bytes()), expected, "{needle}");
+        }
+        // the first occurrence spans the first two segments
+        let mut sut: SegmentArray<u8> = SegmentArray::new();
+        sut.extend_from_slice_copy(&[b'a'; 60]);
+        sut.extend_from_slice_copy(b"needle");
+        sut.extend_from_slice_copy(&[b'a'; 200]);
+        sut.extend_from_slice_copy(b"needle");
+        assert_eq!(sut.find_bytes(b"needle"), Some(60));
+        assert_eq!(sut.find_bytes(b""), Some(0));
+        assert_eq!(sut.find_bytes(&[b'a'; 300]), None);
+    }
+
+    #[test]
+    fn test_as_io_slices() {
+        let empty: SegmentArray<u8> = SegmentArray::new();
+        assert_eq!(empty.as_io_slices().count(), 0);
+        let sut: SegmentArray<u8> = (0..1000).map(|v| v as u8).collect();
+        let lengths: Vec<usize> = sut.as_io_slices().map(|s| s.len()).collect();
+        assert_eq!(lengths, vec![64, 128, 256, 512, 40]);
+    }
+
+    #[test]
+    fn test_write_all_vectored_to() {
+        let sut: SegmentArray<u8> = (0..5000).map(|v| v as u8).collect();
+        let mut output: Vec<u8> = Vec::new();
+        sut.write_all_vectored_to(&mut output).unwrap();
+        assert_eq!(output, sut.to_vec_copy());
+
+        let mut trickle = Trickle {
+            written: Vec::new(),
+            calls: 0,
+        };
+        sut.write_all_vectored_to(&mut trickle).unwrap();
+        assert_eq!(trickle.written, sut.to_vec_copy());
+
+        let empty: SegmentArray<u8> = SegmentArray::new();
+        empty.write_all_vectored_to(&mut Full).unwrap();
+        let err = sut.write_all_vectored_to(&mut Full).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    }
+}
@@ -0,0 +1,175 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Differential testing harness that applies the same sequence of operations
+//! to a segment array and a `Vec` and asserts that they behave identically.
+//! The operations can be generated by [arbitrary](https://crates.io/crates/arbitrary),
+//! making the harness suitable for use with `cargo fuzz`:
+//!
+//! ```ignore
+//! fuzz_target!(|ops: Vec<segment_array::fuzzing::Op>| {
+//!     segment_array::fuzzing::apply(&ops);
+//! });
+//! ```
+
+use super::SegmentArray;
+
+/// Operation to be applied to both the segment array and the vector.
+///
+/// Offsets are reduced modulo the length of the collections where an
+/// out-of-bounds offset would panic, allowing any generated value to be used.
+#[derive(Clone, Debug, arbitrary::Arbitrary)]
+pub enum Op {
+    /// Append the value to the end.
+    Push(u32),
+    /// Remove the last value.
+    Pop,
+    /// Remove the last value if it is even.
+    PopIfEven,
+    /// Remove all values.
+    Clear,
+    /// Retrieve the value at the offset, which may be out of bounds.
+    Get(usize),
+    /// Replace the value at the offset.
+    Set(usize, u32),
+    /// Remove the value at the offset, replacing it with the last value.
+    SwapRemove(usize),
+    /// Exchange the values at the two offsets.
+    Swap(usize, usize),
+    /// Compare the first N values produced by the iterators.
+    IterateN(usize),
+    /// Consume a clone with `into_iter()`, stopping after N values.
+    IntoIterN(usize),
+    /// Append all of the values.
+    ExtendFromSlice(Vec<u32>),
+    /// Reserve capacity for additional values.
+    Reserve(u16),
+    /// Replace the array with a clone of itself.
+    Clone,
+    /// Move all values out of the array and back in again.
+    DrainToVec,
+}
+
+/// Applies the operations to both a segment array and a `Vec`, panicking if
+/// their behavior differs at any point.
+pub fn apply(ops: &[Op]) {
+    let mut array: SegmentArray<String> = SegmentArray::new();
+    let mut vec: Vec<String> = Vec::new();
+    for op in ops {
+        match op {
+            Op::Push(value) => {
+                array.push(value.to_string());
+                vec.push(value.to_string());
+            }
+            Op::Pop => assert_eq!(array.pop(), vec.pop()),
+            Op::PopIfEven => {
+                let even = |v: &String| v.parse::<u32>().unwrap() % 2 == 0;
+                let expected = if vec.last().is_some_and(even) {
+                    vec.pop()
+                } else {
+                    None
+                };
+                assert_eq!(array.pop_if(|v| even(v)), expected);
+            }
+            Op::Clear => {
+                array.clear();
+                vec.clear();
+            }
+            Op::Get(index) => {
+                let index = index % (vec.len() + 1);
+                assert_eq!(array.get(index), vec.get(index));
+            }
+            Op::Set(index, value) => {
+                if !vec.is_empty() {
+                    let index = index % vec.len();
+                    array[index] = value.to_string();
+                    vec[index] = value.to_string();
+                }
+            }
+            Op::SwapRemove(index) => {
+                if !vec.is_empty() {
+                    let index = index % vec.len();
+                    assert_eq!(array.swap_remove(index), vec.swap_remove(index));
+                }
+            }
+            Op::Swap(a, b) => {
+                if !vec.is_empty() {
+                    let a = a % vec.len();
+                    let b = b % vec.len();
+                    array.swap(a, b);
+                    vec.swap(a, b);
+                }
+            }
+            Op::IterateN(n) => {
+                assert!(array.iter().take(*n).eq(vec.iter().take(*n)));
+            }
+            Op::IntoIterN(n) => {
+                let actual = array.clone().into_iter().take(*n);
+                assert!(actual.eq(vec.clone().into_iter().take(*n)));
+            }
+            Op::ExtendFromSlice(values) => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                array.extend_from_slice(&values);
+                vec.extend_from_slice(&values);
+            }
+            Op::Reserve(additional) => array.reserve(*additional as usize),
+            Op::Clone => array = array.clone(),
+            Op::DrainToVec => {
+                let drained = array.drain_to_vec();
+                assert_eq!(drained, vec);
+                assert!(array.is_empty());
+                array = SegmentArray::from(drained);
+            }
+        }
+        assert_eq!(array.len(), vec.len());
+        assert_eq!(array.is_empty(), vec.is_empty());
+        assert!(array.capacity() >= array.len());
+    }
+    assert!(array.iter().eq(vec.iter()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arbitrary::{Arbitrary, Unstructured};
+
+    #[test]
+    fn test_apply_sequence() {
+        let mut ops: Vec<Op> = (0..300).map(Op::Push).collect();
+        ops.push(Op::IterateN(1000));
+        ops.push(Op::SwapRemove(10));
+        ops.push(Op::Swap(0, 250));
+        ops.push(Op::Set(64, 7));
+        ops.push(Op::IntoIterN(100));
+        ops.push(Op::Clone);
+        ops.extend((0..100).map(|_| Op::PopIfEven));
+        ops.extend((0..250).map(|_| Op::Pop));
+        ops.push(Op::Get(3));
+        ops.push(Op::Reserve(500));
+        ops.push(Op::ExtendFromSlice((0..100).collect()));
+        ops.push(Op::DrainToVec);
+        ops.push(Op::Clear);
+        ops.push(Op::Pop);
+        apply(&ops);
+    }
+
+    #[test]
+    fn test_apply_arbitrary() {
+        // generate operations from pseudo-random bytes
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..50 {
+            let bytes: Vec<u8> = (0..4096)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect();
+            let mut unstructured = Unstructured::new(&bytes);
+            let ops: Vec<Op> = Vec::arbitrary(&mut unstructured).unwrap();
+            apply(&ops);
+        }
+    }
+}
@@ -24,10 +24,12 @@
 //! * First segment has a capacity of 64
 //! * Each segment is double the size of its predecessor
 //! * Total capacity of 4,294,967,232 items
+//! * Elements of at most 4,294,967,295 bytes on 64-bit targets, checked at
+//!   compile time
 //!
 //! # Memory Usage
 //!
-//! An empty segment array is approximately 224 bytes in size and it will have a
+//! An empty segment array is approximately 280 bytes in size and it will have a
 //! space overhead on the order of O(N) due to its geometric growth function
 //! (like `std::vec::Vec`). As elements are added the array will grow by
 //! allocating additional segments. Likewise, as elements are removed from the
@@ -37,124 +39,755 @@
 //!
 //! Because this data structure is allocating memory, copying bytes using
 //! pointers, and de-allocating memory as needed, there are many `unsafe` blocks
-//! throughout the code.
-
-use std::alloc::{Layout, alloc, dealloc, handle_alloc_error};
+//! in the code that manages the segments.
+//!
+//! When built with `RUSTFLAGS="--cfg segment_array_safe"`, the segments are
+//! instead held in vectors and the crate is built with
+//! `#![forbid(unsafe_code)]`, at some cost in performance. The API is the
+//! same, except that `push_unchecked()` and `pop_unchecked()` are safe
+//! functions, [`Zeroable`] is a safe trait with a `ZERO` constant, and
+//! [`SegmentStorage`] only decides whether a segment may be allocated rather
+//! than providing its memory; the storage backends other than
+//! [`storage::Heap`] are not available. None of these can be expressed
+//! without `unsafe`, hence this is a configuration option rather than a Cargo
+//! feature: it is chosen once, by whoever builds the final binary, and cannot
+//! be switched on for every user of the crate by one of its dependents. The
+//! `asan`, `mmap`, `nightly`, `poison`, and `valgrind` features have no effect
+//! in this configuration.
+//!
+//! # Features
+//!
+//! With the `nightly` feature enabled, the iterators implement the unstable
+//! `TrustedLen` marker trait, allowing conversions such as `Vec::from_iter()`
+//! to write directly into an allocation of the exact size. The other unstable
+//! traits (`InPlaceIterable`, `SpecExtend`) are only usable by the iterators
+//! in the standard library.
+
+#![cfg_attr(
+    all(feature = "nightly", not(segment_array_safe)),
+    feature(trusted_len)
+)]
+#![cfg_attr(segment_array_safe, forbid(unsafe_code))]
+
+use std::alloc::Layout;
+#[cfg(feature = "rayon")]
+use std::cmp::Ordering;
 use std::fmt;
-use std::iter::{FromIterator, Iterator};
-use std::ops::{Index, IndexMut};
+use std::iter::{FromIterator, FusedIterator, Iterator};
+use std::ops::{Bound, Index, IndexMut, RangeBounds};
+use std::sync::Arc;
+
+mod budget;
+mod bytes;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod raw;
+#[cfg(not(segment_array_safe))]
+mod sanitize;
+mod search;
+// the segments are held by raw pointers, unless the `segment_array_safe`
+// configuration selects the implementation that is written in safe code
+#[cfg_attr(segment_array_safe, path = "safe/segments.rs")]
+mod segments;
+pub mod stats;
+#[cfg_attr(segment_array_safe, path = "safe/storage.rs")]
+pub mod storage;
+
+pub use budget::MemoryBudget;
+pub use bytes::{Lines, Split, StrChunks, Utf8Error};
+use raw::{
+    MAX_SEGMENT_COUNT, capacity_for_segment_count, check_element_size, locate, segments_for_len,
+    slots_in_segment,
+};
+pub use search::SearchIndex;
+use segments::Segments;
+pub use segments::{PreparedSegment, Zeroable};
+#[cfg(feature = "test-support")]
+pub use storage::FailingStorage;
+use storage::Heap;
+pub use storage::SegmentStorage;
 
-//
-// An individual segment can never be larger than 9,223,372,036,854,775,807
-// bytes due to the mechanics of the Rust memory allocator.
-//
-// 26 segments with 6 skipped segments with each segment doubling in size
-// results in the last segment having 2,147,483,648 items
-//
-// 9,223,372,036,854,775,807 bytes divided by 2,147,483,648 items yields a
-// maximum item size of 4,294,967,296 bytes
-//
-const MAX_SEGMENT_COUNT: usize = 26;
-
-// Segments of size 1, 2, 4, 8, 16, and 32 are not used at all (that is, the
-// smallest (first) segment is 64 elements in size) to avoid the overhead of
-// such tiny arrays.
-const SMALL_SEGMENTS_TO_SKIP: usize = 6;
-const SMALL_SEGMENTS_CAPACITY: usize = 1 << SMALL_SEGMENTS_TO_SKIP;
-
-// Calculates the number of elements that will fit into the given segment.
-#[inline]
-fn slots_in_segment(segment: usize) -> usize {
-    SMALL_SEGMENTS_CAPACITY << segment
+///
+/// Details of a segment that was added to or removed from an array, as passed
+/// to the callback registered with [`SegmentArray::set_allocation_hook`].
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentEvent {
+    /// A segment was allocated.
+    Allocated {
+        /// number of the segment, from 0 to 25
+        segment: usize,
+        /// layout of the allocated memory
+        layout: Layout,
+    },
+    /// A segment is about to be deallocated.
+    Deallocated {
+        /// number of the segment, from 0 to 25
+        segment: usize,
+        /// layout of the allocated memory
+        layout: Layout,
+    },
+}
+
+///
+/// Counters that accumulate over the lifetime of an array, as returned by
+/// [`SegmentArray::stats`].
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LifetimeStats {
+    /// greatest number of elements held by the array
+    pub peak_len: usize,
+    /// number of segments allocated
+    pub segments_allocated: usize,
+    /// total number of bytes requested for all allocated segments
+    pub bytes_allocated: usize,
+}
+
+impl LifetimeStats {
+    // The peak length is only recorded before the length decreases, keeping
+    // the cost of tracking it out of the push path.
+    #[inline]
+    fn record_len(&mut self, len: usize) {
+        if len > self.peak_len {
+            self.peak_len = len;
+        }
+    }
+}
+
+///
+/// Error returned by the fallible allocation methods, such as
+/// [`SegmentArray::try_reserve`].
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TryReserveError {
+    /// The requested capacity exceeds the maximum capacity of the array.
+    CapacityOverflow,
+    /// The storage was unable to allocate a segment.
+    AllocError {
+        /// layout of the segment that could not be allocated
+        layout: Layout,
+    },
+    /// Allocating a segment would exceed the [`MemoryBudget`] of the array.
+    BudgetExceeded {
+        /// size in bytes of the segment that could not be allocated
+        requested: usize,
+        /// number of bytes remaining in the budget
+        remaining: usize,
+    },
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => {
+                write!(f, "requested capacity exceeds the maximum capacity")
+            }
+            TryReserveError::AllocError { layout } => {
+                write!(f, "memory allocation of {} bytes failed", layout.size())
+            }
+            TryReserveError::BudgetExceeded {
+                requested,
+                remaining,
+            } => write!(
+                f,
+                "memory budget exceeded: requested {requested} bytes with {remaining} remaining"
+            ),
+        }
+    }
 }
 
-// Calculates the overall capacity for all segments up to the given segment.
-#[inline]
-fn capacity_for_segment_count(segment: usize) -> usize {
-    (SMALL_SEGMENTS_CAPACITY << segment) - SMALL_SEGMENTS_CAPACITY
+impl std::error::Error for TryReserveError {}
+
+///
+/// Behavior when the destructor of an element panics while the array is being
+/// cleared or dropped, as set with [`SegmentArray::set_drop_policy`].
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drop the remaining elements and deallocate the segments, then resume
+    /// unwinding. A second panic while doing so will abort the process.
+    #[default]
+    Unwind,
+    /// Abort the process as soon as a destructor panics, without dropping
+    /// any further elements or deallocating the segments. The elements are
+    /// then dropped one at a time rather than a segment at a time.
+    Abort,
 }
 
+/// Callback registered with [`SegmentArray::set_allocation_hook`].
+type AllocationHook = Arc<dyn Fn(&SegmentEvent) + Send + Sync>;
+
 ///
 /// Append-only growable array that uses a list of progressivly larger segments
 /// to avoid the allocate-and-copy that many growable data structures typically
 /// employ.
 ///
-pub struct SegmentArray<T> {
+/// The memory for the segments is provided by the [`SegmentStorage`], which by
+/// default is the global heap.
+///
+pub struct SegmentArray<T, S: SegmentStorage = Heap> {
     // number of elements stored in the array
     count: usize,
-    // number of allocated segments
-    used_segments: usize,
-    // pointers to allocated segments (0 to used_segments-1)
-    segments: [*mut T; MAX_SEGMENT_COUNT],
+    // allocated segments that hold the values
+    segments: Segments<T>,
+    // counters that persist for the lifetime of the array
+    stats: LifetimeStats,
+    // callback invoked when segments are allocated or deallocated
+    hook: Option<AllocationHook>,
+    // allocated bytes beyond which an event will be emitted
+    #[cfg(feature = "tracing")]
+    trace_threshold: Option<usize>,
+    // limit on the size of the allocated segments
+    budget: Option<MemoryBudget>,
+    // what to do when the destructor of an element panics
+    drop_policy: DropPolicy,
+    // source of the memory for the segments
+    storage: S,
 }
 
 impl<T> SegmentArray<T> {
     /// Return an empty segment array with zero capacity.
     ///
-    /// Note that pre-allocating capacity has no benefit with this data
+    /// Note that pre-allocating capacity has little benefit with this data
     /// structure since append operations are always constant time and
     /// no reallocation and copy is ever performed.
-    pub fn new() -> Self {
+    ///
+    /// Since this is a `const fn`, an array can be placed in a static:
+    ///
+    /// ```
+    /// # use segment_array::SegmentArray;
+    /// # use std::sync::Mutex;
+    /// static EVENTS: Mutex<SegmentArray<u64>> = Mutex::new(SegmentArray::new());
+    /// EVENTS.lock().unwrap().push(1);
+    /// assert_eq!(EVENTS.lock().unwrap().len(), 1);
+    /// ```
+    pub const fn new() -> Self {
+        Self::new_in(Heap)
+    }
+
+    /// Return a segment array of the given length in which every element is
+    /// zero, using allocations that are zero-filled by the allocator (and
+    /// possibly the operating system) rather than writing each element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length exceeds the maximum capacity of the array.
+    ///
+    /// # Time complexity
+    ///
+    /// O(s) where s is the number of segments to be allocated.
+    pub fn with_zeroed(len: usize) -> Self
+    where
+        T: Zeroable,
+    {
+        Self::with_zeroed_in(len, Heap)
+    }
+
+    /// Adds a segment that was allocated ahead of time, possibly on another
+    /// thread, if it is the next segment to be allocated. Returns false, and
+    /// deallocates the segment, if the array has since changed such that it
+    /// needs a different segment, or if the segment would exceed the memory
+    /// budget of the array.
+    ///
+    /// Prepared segments are allocated from the global allocator, so only
+    /// arrays that use [`Heap`] storage can install them.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    pub fn install_segment(&mut self, prepared: PreparedSegment<T>) -> bool {
+        let segment = prepared.segment();
+        if segment != self.segments.used() {
+            return false;
+        }
+        let segment_len = slots_in_segment(segment);
+        let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
+        if self
+            .budget
+            .as_ref()
+            .is_some_and(|budget| !budget.try_acquire(layout.size()))
+        {
+            return false;
+        }
+        self.segments.install(prepared);
+        self.notify(true, segment);
+        #[cfg(feature = "tracing")]
+        self.trace_allocation(layout);
+        true
+    }
+
+    /// Collects a uniformly random sample of up to `amount` elements from an
+    /// iterator of unknown length using reservoir sampling.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements produced by the iterator.
+    #[cfg(feature = "rand")]
+    pub fn sample_from_iter<I, R>(iter: I, rng: &mut R, amount: usize) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        R: rand::Rng + ?Sized,
+    {
+        let mut arr: SegmentArray<T> = SegmentArray::new();
+        for (seen, value) in iter.into_iter().enumerate() {
+            if seen < amount {
+                arr.push(value);
+            } else {
+                let index = rng.random_range(0..=seen);
+                if index < amount {
+                    arr[index] = value;
+                }
+            }
+        }
+        arr
+    }
+}
+
+impl<T, S: SegmentStorage> SegmentArray<T, S> {
+    const ELEMENT_SIZE_CHECK: () = check_element_size::<T>();
+
+    /// Return an empty segment array with zero capacity that allocates its
+    /// segments from the given storage.
+    pub const fn new_in(storage: S) -> Self {
+        // reject element types that are too large at compile time
+        let () = Self::ELEMENT_SIZE_CHECK;
         Self {
             count: 0,
-            used_segments: 0,
-            segments: [std::ptr::null_mut::<T>(); MAX_SEGMENT_COUNT],
+            segments: Segments::new(),
+            stats: LifetimeStats {
+                peak_len: 0,
+                segments_allocated: 0,
+                bytes_allocated: 0,
+            },
+            hook: None,
+            #[cfg(feature = "tracing")]
+            trace_threshold: None,
+            budget: None,
+            drop_policy: DropPolicy::Unwind,
+            storage,
+        }
+    }
+
+    /// Return an empty array with the same configuration as this one, such as
+    /// the allocation hook.
+    fn new_like<U>(&self) -> SegmentArray<U, S> {
+        let mut arr: SegmentArray<U, S> = SegmentArray::new_in(self.storage.clone());
+        arr.hook.clone_from(&self.hook);
+        arr.budget.clone_from(&self.budget);
+        arr.drop_policy = self.drop_policy;
+        #[cfg(feature = "tracing")]
+        {
+            arr.trace_threshold = self.trace_threshold;
+        }
+        arr
+    }
+
+    /// Registers a callback that will be invoked whenever a segment is added
+    /// to or removed from the array, replacing any previous callback. Clones
+    /// of the array share the same callback.
+    ///
+    /// The callback is invoked after a segment has been allocated, and before
+    /// a segment is deallocated.
+    pub fn set_allocation_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&SegmentEvent) + Send + Sync + 'static,
+    {
+        self.hook = Some(Arc::new(hook));
+    }
+
+    /// Removes the callback registered with [`Self::set_allocation_hook`].
+    pub fn clear_allocation_hook(&mut self) {
+        self.hook = None;
+    }
+
+    /// Returns the counters that have accumulated over the lifetime of the
+    /// array, or since the last call to [`Self::reset_stats`].
+    pub fn stats(&self) -> LifetimeStats {
+        let mut stats = self.stats;
+        stats.record_len(self.count);
+        stats
+    }
+
+    /// Resets the lifetime counters, such that the peak length is the current
+    /// length and the allocation counts are zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = LifetimeStats::default();
+    }
+
+    /// Limits the total size of the segments allocated by this array (and any
+    /// others sharing the same budget), replacing any previous budget. Arrays
+    /// derived from this one, such as clones, share the same budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, leaving the array unchanged, if the segments that are
+    /// already allocated exceed the remaining budget.
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) -> Result<(), TryReserveError> {
+        let bytes = capacity_for_segment_count(self.segments.used()) * size_of::<T>();
+        if !budget.try_acquire(bytes) {
+            return Err(TryReserveError::BudgetExceeded {
+                requested: bytes,
+                remaining: budget.remaining(),
+            });
+        }
+        self.clear_memory_budget();
+        self.budget = Some(budget);
+        Ok(())
+    }
+
+    /// Removes the budget set with [`Self::set_memory_budget`].
+    pub fn clear_memory_budget(&mut self) {
+        if let Some(budget) = self.budget.take() {
+            budget.release(capacity_for_segment_count(self.segments.used()) * size_of::<T>());
+        }
+    }
+
+    /// Returns the budget set with [`Self::set_memory_budget`], if any.
+    pub fn memory_budget(&self) -> Option<&MemoryBudget> {
+        self.budget.as_ref()
+    }
+
+    /// Sets the behavior when the destructor of an element panics while the
+    /// array is being cleared or dropped. The default is to drop the remaining
+    /// elements before resuming the unwind.
+    pub fn set_drop_policy(&mut self, policy: DropPolicy) {
+        self.drop_policy = policy;
+    }
+
+    /// Returns the policy set with [`Self::set_drop_policy`].
+    pub fn drop_policy(&self) -> DropPolicy {
+        self.drop_policy
+    }
+
+    /// Invoke the allocation hook, if any, for the given segment, and update
+    /// the lifetime counters.
+    fn notify(&mut self, allocated: bool, segment: usize) {
+        if allocated {
+            let size = slots_in_segment(segment) * size_of::<T>();
+            self.stats.segments_allocated += 1;
+            self.stats.bytes_allocated += size;
+        }
+        if let Some(hook) = self.hook.as_ref() {
+            let layout =
+                Layout::array::<T>(slots_in_segment(segment)).expect("unexpected overflow");
+            let event = if allocated {
+                SegmentEvent::Allocated { segment, layout }
+            } else {
+                SegmentEvent::Deallocated { segment, layout }
+            };
+            hook(&event);
         }
     }
 
+    /// Return a segment array of the given length in which every element is
+    /// zero, using segments that are zero-filled by the given storage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length exceeds the maximum capacity of the array.
+    ///
+    /// # Time complexity
+    ///
+    /// O(s) where s is the number of segments to be allocated.
+    pub fn with_zeroed_in(len: usize, storage: S) -> Self
+    where
+        T: Zeroable,
+    {
+        let mut arr = Self::new_in(storage);
+        while arr.capacity() < len {
+            arr.allocate_segment(true);
+        }
+        arr.segments.init_zeroed(len);
+        arr.count = len;
+        arr
+    }
+
     /// Appends an element to the back of a collection.
     ///
     /// # Panics
     ///
-    /// Panics if a new segment is allocated that would exceed `isize::MAX` _bytes_.
+    /// Panics if a new segment is allocated that would exceed `isize::MAX` _bytes_,
+    /// or the memory budget of the array.
     ///
     /// # Time complexity
     ///
     /// Constant time.
     pub fn push(&mut self, value: T) {
-        if self.count >= capacity_for_segment_count(self.used_segments) {
-            assert!(
-                self.used_segments < MAX_SEGMENT_COUNT,
-                "maximum number of segments exceeded"
-            );
-            let segment_len = slots_in_segment(self.used_segments);
-            // overflowing the allocator is very unlikely as the item size would
-            // have to be very large
-            let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
-            unsafe {
-                let ptr = alloc(layout).cast::<T>();
-                if ptr.is_null() {
-                    handle_alloc_error(layout);
-                }
-                self.segments[self.used_segments] = ptr;
+        if self.count >= self.capacity() {
+            self.allocate_segment(false);
+        }
+        self.segments.write(self.count, value);
+        self.count += 1;
+    }
+
+    /// Allocate the next segment, increasing the capacity of the array. If
+    /// `zeroed` is true, the memory of the segment will be filled with zeros.
+    fn allocate_segment(&mut self, zeroed: bool) {
+        match self.try_allocate_segment(zeroed) {
+            Ok(()) => {}
+            Err(TryReserveError::CapacityOverflow) => {
+                panic!("maximum number of segments exceeded")
             }
-            self.used_segments += 1;
+            Err(TryReserveError::AllocError { layout }) => self.storage.allocation_failed(layout),
+            Err(err) => panic!("{err}"),
         }
+    }
 
-        let segment = ((self.count >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-        let slot = self.count - capacity_for_segment_count(segment);
-        unsafe {
-            std::ptr::write(self.segments[segment].add(slot), value);
+    /// Allocate the next segment as with [`Self::allocate_segment`], returning
+    /// an error if the segment could not be allocated.
+    fn try_allocate_segment(&mut self, zeroed: bool) -> Result<(), TryReserveError> {
+        let segment = self.segments.used();
+        if segment >= MAX_SEGMENT_COUNT {
+            return Err(TryReserveError::CapacityOverflow);
+        }
+        let segment_len = slots_in_segment(segment);
+        // overflowing the allocator is very unlikely as the item size would
+        // have to be very large
+        let layout =
+            Layout::array::<T>(segment_len).map_err(|_| TryReserveError::CapacityOverflow)?;
+        let over_budget = self
+            .budget
+            .as_ref()
+            .filter(|b| !b.try_acquire(layout.size()));
+        if let Some(budget) = over_budget {
+            return Err(TryReserveError::BudgetExceeded {
+                requested: layout.size(),
+                remaining: budget.remaining(),
+            });
         }
-        self.count += 1;
+        if !self.segments.allocate(&self.storage, layout, zeroed) {
+            if let Some(budget) = self.budget.as_ref() {
+                budget.release(layout.size());
+            }
+            return Err(TryReserveError::AllocError { layout });
+        }
+        self.notify(true, segment);
+        #[cfg(feature = "tracing")]
+        self.trace_allocation(layout);
+        Ok(())
+    }
+
+    /// Emit events for a newly allocated segment, including when the total
+    /// allocated size crosses the threshold.
+    #[cfg(feature = "tracing")]
+    fn trace_allocation(&self, layout: Layout) {
+        let segment = self.segments.used() - 1;
+        tracing::debug!(segment, bytes = layout.size(), "segment allocated");
+        if let Some(threshold) = self.trace_threshold {
+            let allocated = self.capacity() * size_of::<T>();
+            if allocated >= threshold && allocated - layout.size() < threshold {
+                tracing::info!(
+                    segment,
+                    allocated,
+                    threshold,
+                    "segment array exceeded allocation threshold"
+                );
+            }
+        }
+    }
+
+    /// Sets the total number of allocated bytes that, when exceeded by the
+    /// allocation of a segment, will emit an event at the `INFO` level.
+    #[cfg(feature = "tracing")]
+    pub fn set_trace_threshold(&mut self, bytes: Option<usize>) {
+        self.trace_threshold = bytes;
+    }
+
+    /// Deallocate the last segment, decreasing the capacity of the array.
+    fn deallocate_segment(&mut self) {
+        let segment = self.segments.used() - 1;
+        self.notify(false, segment);
+        let segment_len = slots_in_segment(segment);
+        let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
+        self.segments.deallocate(&self.storage, layout);
+        if let Some(budget) = self.budget.as_ref() {
+            budget.release(layout.size());
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(segment, bytes = layout.size(), "segment deallocated");
+    }
+
+    /// Allocate segments until the capacity is at least the given length.
+    fn grow_to(&mut self, len: usize) {
+        while capacity_for_segment_count(self.segments.used()) < len {
+            self.allocate_segment(false);
+        }
+    }
+
+    /// Returns the occupied portions of the segments that make up the given
+    /// range of elements, in order.
+    fn range_slices(&self, start: usize, end: usize) -> impl Iterator<Item = &[T]> {
+        self.segments.slices(start, end)
+    }
+
+    /// Converts the range into start and end offsets, panicking if the range
+    /// is out of bounds, in the same manner as slices.
+    fn resolve_range<R: RangeBounds<usize>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start
+                .checked_add(1)
+                .expect("attempted to index slice from after maximum usize"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end
+                .checked_add(1)
+                .expect("attempted to index slice up to maximum usize"),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.count,
+        };
+        if start > end {
+            panic!("slice index starts at {start} but ends at {end}");
+        }
+        if end > self.count {
+            panic!(
+                "range end index {end} out of range for slice of length {}",
+                self.count
+            );
+        }
+        (start, end)
     }
 
     /// Deallocate segments as they become empty.
     fn shrink(&mut self) {
-        if self.used_segments > 0
-            && self.count <= capacity_for_segment_count(self.used_segments - 1)
+        if self.segments.used() > 0
+            && self.count <= capacity_for_segment_count(self.segments.used() - 1)
         {
-            let segment = self.used_segments - 1;
-            let segment_len = slots_in_segment(segment);
-            let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
-            unsafe {
-                dealloc(self.segments[segment] as *mut u8, layout);
-            }
-            self.segments[segment] = std::ptr::null_mut();
-            self.used_segments -= 1;
+            self.deallocate_segment();
+        }
+    }
+
+    /// Appends an element without checking for spare capacity.
+    ///
+    /// # Safety
+    ///
+    /// The length of the array must be less than its capacity, as ensured by
+    /// a prior call to [`Self::reserve`].
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    #[cfg(not(segment_array_safe))]
+    pub unsafe fn push_unchecked(&mut self, value: T) {
+        debug_assert!(self.count < self.capacity());
+        self.segments.write(self.count, value);
+        self.count += 1;
+    }
+
+    /// Appends an element, having already reserved the capacity with
+    /// [`Self::reserve`].
+    ///
+    /// When built with `segment_array_safe` this function is safe, though
+    /// calls to it may still be wrapped in `unsafe` blocks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the array is not less than its capacity.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    #[cfg(segment_array_safe)]
+    pub fn push_unchecked(&mut self, value: T) {
+        assert!(self.count < self.capacity(), "no spare capacity");
+        self.segments.write(self.count, value);
+        self.count += 1;
+    }
+
+    /// Reserves capacity for at least `additional` more elements by
+    /// allocating segments in advance.
+    ///
+    /// Note that segments beyond those needed by the current length may be
+    /// deallocated again as elements are removed from the array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity exceeds the maximum capacity of the array.
+    ///
+    /// # Time complexity
+    ///
+    /// O(s) where s is the number of segments to be allocated.
+    pub fn reserve(&mut self, additional: usize) {
+        let len = self
+            .count
+            .checked_add(additional)
+            .expect("capacity overflow");
+        self.grow_to(len);
+    }
+
+    /// Reserves capacity for at least `additional` more elements as with
+    /// [`Self::reserve`], returning an error rather than panicking or
+    /// aborting if the capacity cannot be allocated.
+    ///
+    /// Segments allocated prior to a failure are retained by the array.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new capacity exceeds the maximum capacity of
+    /// the array, or if the storage fails to allocate a segment.
+    ///
+    /// # Time complexity
+    ///
+    /// O(s) where s is the number of segments to be allocated.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let len = self
+            .count
+            .checked_add(additional)
+            .filter(|len| *len <= capacity_for_segment_count(MAX_SEGMENT_COUNT))
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        while capacity_for_segment_count(self.segments.used()) < len {
+            self.try_allocate_segment(false)?;
+        }
+        Ok(())
+    }
+
+    /// Appends an element to the back of a collection, returning an error
+    /// rather than panicking or aborting if a segment cannot be allocated, in
+    /// which case the element is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the array is full, or if the storage fails to
+    /// allocate a segment.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    pub fn try_push(&mut self, value: T) -> Result<(), TryReserveError> {
+        self.try_reserve(1)?;
+        self.push(value);
+        Ok(())
+    }
+
+    /// Allocates the segment following the last one that contains elements,
+    /// if it has not already been allocated, so that the allocation does not
+    /// occur during a later `push()`. Returns true if a segment was allocated.
+    ///
+    /// Note that removing elements from the array may deallocate the prepared
+    /// segment before it is used.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    pub fn prepare_next_segment(&mut self) -> bool {
+        if self.segments.used() > segments_for_len(self.count)
+            || self.segments.used() >= MAX_SEGMENT_COUNT
+        {
+            false
+        } else {
+            self.allocate_segment(false);
+            true
         }
     }
 
+    /// Returns the number of the segment that will be allocated next, for use
+    /// with [`PreparedSegment::new`].
+    pub fn next_segment(&self) -> usize {
+        self.segments.used()
+    }
+
     /// Appends an element if there is sufficient spare capacity, otherwise an
     /// error is returned with the element.
     ///
@@ -162,7 +795,7 @@ impl<T> SegmentArray<T> {
     ///
     /// Constant time.
     pub fn push_within_capacity(&mut self, value: T) -> Result<(), T> {
-        if self.count >= capacity_for_segment_count(self.used_segments) {
+        if self.count >= capacity_for_segment_count(self.segments.used()) {
             Err(value)
         } else {
             self.push(value);
@@ -178,17 +811,53 @@ impl<T> SegmentArray<T> {
     /// Constant time.
     pub fn pop(&mut self) -> Option<T> {
         if self.count > 0 {
+            self.stats.record_len(self.count);
             self.count -= 1;
-            let segment = ((self.count >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            let slot = self.count - capacity_for_segment_count(segment);
-            let value = unsafe { Some((self.segments[segment].add(slot)).read()) };
+            let value = self.segments.read(self.count);
             self.shrink();
-            value
+            Some(value)
         } else {
             None
         }
     }
 
+    /// Removes the last element from the array and returns it, without
+    /// checking if the array is empty.
+    ///
+    /// # Safety
+    ///
+    /// The array must not be empty.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    #[cfg(not(segment_array_safe))]
+    pub unsafe fn pop_unchecked(&mut self) -> T {
+        debug_assert!(self.count > 0);
+        self.stats.record_len(self.count);
+        self.count -= 1;
+        let value = self.segments.read(self.count);
+        self.shrink();
+        value
+    }
+
+    /// Removes the last element from the array and returns it.
+    ///
+    /// When built with `segment_array_safe` this function is safe, though
+    /// calls to it may still be wrapped in `unsafe` blocks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the array is empty.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    #[cfg(segment_array_safe)]
+    pub fn pop_unchecked(&mut self) -> T {
+        self.pop().expect("array is empty")
+    }
+
     /// Removes and returns the last element from a vector if the predicate
     /// returns true, or None if the predicate returns false or the vector is
     /// empty (the predicate will not be called in that case).
@@ -222,7 +891,7 @@ impl<T> SegmentArray<T> {
     ///
     /// Constant time.
     pub fn capacity(&self) -> usize {
-        capacity_for_segment_count(self.used_segments)
+        capacity_for_segment_count(self.segments.used())
     }
 
     /// Returns true if the array has a length of 0.
@@ -243,67 +912,582 @@ impl<T> SegmentArray<T> {
         if index >= self.count {
             None
         } else {
-            let segment = ((index >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            let slot = index - capacity_for_segment_count(segment);
-            unsafe { (self.segments[segment].add(slot)).as_ref() }
+            Some(self.segments.get(index))
         }
     }
 
-    /// Returns a mutable reference to an element.
+    /// Retrieve references to the elements at each of the given offsets, or
+    /// `None` if any offset is out of bounds.
+    ///
+    /// The offsets are first sorted, such that the elements of each segment
+    /// are prefetched together and in address order, after which the
+    /// references are collected in the order given.
     ///
     /// # Time complexity
     ///
-    /// Constant time.
-    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
-        if index >= self.count {
-            None
-        } else {
-            let segment = ((index >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            let slot = index - capacity_for_segment_count(segment);
-            unsafe { (self.segments[segment].add(slot)).as_mut() }
-        }
+    /// O(N log N) where N is the number of offsets.
+    pub fn get_many<const N: usize>(&self, indices: [usize; N]) -> Option<[&T; N]> {
+        if indices.iter().any(|index| *index >= self.count) {
+            return None;
+        }
+        let mut sorted = indices;
+        self.prefetch_sorted(&mut sorted);
+        Some(indices.map(|index| self.segments.get(index)))
     }
 
-    /// Removes an element from the vector and returns it.
+    /// Returns an iterator that yields a reference to the element at each of
+    /// the given offsets, or `None` for offsets that are out of bounds.
     ///
-    /// The removed element is replaced by the last element of the vector.
+    /// The offsets are taken in batches, each of which is sorted such that
+    /// the elements of each segment are prefetched together and in address
+    /// order, before the references are yielded in the order given.
+    pub fn gather<I>(&self, indices: I) -> impl Iterator<Item = Option<&T>>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut indices = indices.into_iter();
+        let mut batch: Vec<usize> = Vec::new();
+        let mut sorted: Vec<usize> = Vec::new();
+        let mut position = 0;
+        std::iter::from_fn(move || {
+            if position == batch.len() {
+                batch.clear();
+                batch.extend(indices.by_ref().take(GATHER_BATCH_SIZE));
+                sorted.clear();
+                sorted.extend(batch.iter().filter(|index| **index < self.count));
+                self.prefetch_sorted(&mut sorted);
+                position = 0;
+            }
+            let index = *batch.get(position)?;
+            position += 1;
+            Some(self.get(index))
+        })
+    }
+
+    /// Sorts the offsets, all of which must be in bounds, and hints to the
+    /// processor that the elements will soon be read, one segment at a time.
+    fn prefetch_sorted(&self, indices: &mut [usize]) {
+        indices.sort_unstable();
+        for index in indices.iter() {
+            self.segments.prefetch(*index);
+        }
+    }
+
+    /// Returns the offset of the first element for which the predicate
+    /// returns false, given that the array is partitioned such that the
+    /// predicate returns true for all elements before that offset and false
+    /// for all elements after (e.g. `|x| x < key` for a sorted array).
     ///
-    /// This does not preserve ordering of the remaining elements.
+    /// Starting from the `hint` offset, the search gallops outward with
+    /// exponentially increasing steps until the partition point is bracketed
+    /// and then performs a binary search within that range. This is much
+    /// faster than a full binary search when the result is near the hint,
+    /// such as queries for recently appended, time-ordered data.
     ///
     /// # Time complexity
     ///
-    /// Constant time.
-    pub fn swap_remove(&mut self, index: usize) -> T {
-        if index >= self.count {
-            panic!(
-                "swap_remove index (is {index}) should be < len (is {})",
-                self.count
-            );
+    /// O(log d) where d is the distance between the hint and the result.
+    pub fn exponential_partition_point<P>(&self, hint: usize, mut pred: P) -> usize
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let hint = hint.min(self.count);
+        let mut test = |index: usize| pred(self.segments.get(index));
+        let mut lo;
+        let mut hi;
+        let mut bound = 1;
+        if hint < self.count && test(hint) {
+            // gallop toward the end
+            lo = hint + 1;
+            loop {
+                let probe = hint.saturating_add(bound);
+                if probe >= self.count {
+                    hi = self.count;
+                    break;
+                }
+                if test(probe) {
+                    lo = probe + 1;
+                    bound *= 2;
+                } else {
+                    hi = probe;
+                    break;
+                }
+            }
+        } else {
+            // gallop toward the start
+            hi = hint;
+            loop {
+                if bound > hint {
+                    lo = 0;
+                    break;
+                }
+                let probe = hint - bound;
+                if test(probe) {
+                    lo = probe + 1;
+                    break;
+                }
+                hi = probe;
+                bound *= 2;
+            }
         }
-        // retreive the value at index before overwriting
-        let segment = ((index >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-        let slot = index - capacity_for_segment_count(segment);
-        unsafe {
-            let index_ptr = self.segments[segment].add(slot);
-            let value = index_ptr.read();
-            // find the pointer of the last element and copy to index pointer
-            self.count -= 1;
-            let segment = ((self.count >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            let slot = self.count - capacity_for_segment_count(segment);
-            let last_ptr = self.segments[segment].add(slot);
-            std::ptr::copy(last_ptr, index_ptr, 1);
-            self.shrink();
-            value
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if test(mid) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
         }
+        lo
     }
 
-    /// Returns an iterator over the segment array.
+    /// Searches a sorted array for the given key, galloping outward from the
+    /// `hint` offset as described in [`Self::exponential_partition_point`].
     ///
-    /// The iterator yields all items from start to end.
-    pub fn iter(&self) -> SegArrayIter<'_, T> {
-        SegArrayIter {
-            array: self,
-            index: 0,
+    /// Like `slice::binary_search()`, returns `Ok` with the offset of a
+    /// matching element (the first, if there are several), or `Err` with the
+    /// offset where the key could be inserted while maintaining sorted order.
+    ///
+    /// # Time complexity
+    ///
+    /// O(log d) where d is the distance between the hint and the result.
+    pub fn exponential_search(&self, key: &T, hint: usize) -> Result<usize, usize>
+    where
+        T: Ord,
+    {
+        let index = self.exponential_partition_point(hint, |value| value < key);
+        match self.get(index) {
+            Some(value) if value == key => Ok(index),
+            _ => Err(index),
+        }
+    }
+
+    /// Returns the offset of the first occurrence of the needle within the
+    /// array, including occurrences that span segment boundaries, or `None`
+    /// if there is no such occurrence. An empty needle matches at offset 0.
+    ///
+    /// For byte arrays, [`Self::find_bytes`] is considerably faster.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n * m) where n is the number of elements in the array and m is the
+    /// length of the needle.
+    pub fn find(&self, needle: &[T]) -> Option<usize>
+    where
+        T: Eq,
+    {
+        let len = needle.len();
+        if len == 0 {
+            return Some(0);
+        }
+        let mut offset = 0;
+        for slice in self.range_slices(0, self.count) {
+            // occurrences that lie entirely within this segment
+            if let Some(found) = slice.windows(len).position(|window| window == needle) {
+                return Some(offset + found);
+            }
+            // occurrences that continue into the following segments
+            let end = offset + slice.len();
+            for start in (end + 1).saturating_sub(len).max(offset)..end {
+                if start + len > self.count {
+                    return None;
+                }
+                if self.matches_at(start, needle) {
+                    return Some(start);
+                }
+            }
+            offset = end;
+        }
+        None
+    }
+
+    /// Returns true if the elements starting at the given offset are equal to
+    /// the needle, which must not extend beyond the end of the array.
+    fn matches_at(&self, start: usize, needle: &[T]) -> bool
+    where
+        T: Eq,
+    {
+        let mut remaining = needle;
+        for slice in self.range_slices(start, start + needle.len()) {
+            let (head, tail) = remaining.split_at(slice.len());
+            if slice != head {
+                return false;
+            }
+            remaining = tail;
+        }
+        true
+    }
+
+    /// Returns a mutable reference to an element.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.count {
+            None
+        } else {
+            Some(self.segments.get_mut(index))
+        }
+    }
+
+    /// Swaps two elements in the array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` are out of bounds.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if a >= self.count || b >= self.count {
+            panic!(
+                "swap index (is {}) should be < len (is {})",
+                a.max(b),
+                self.count
+            );
+        }
+        self.segments.swap(a, b);
+    }
+
+    /// Shuffles the elements in place using the Fisher-Yates algorithm.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    #[cfg(feature = "rand")]
+    pub fn shuffle<R: rand::Rng + ?Sized>(&mut self, rng: &mut R) {
+        for index in (1..self.count).rev() {
+            let other = rng.random_range(0..=index);
+            self.swap(index, other);
+        }
+    }
+
+    /// Returns a reference to a randomly chosen element, or `None` if the
+    /// array is empty.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    #[cfg(feature = "rand")]
+    pub fn choose<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Option<&T> {
+        if self.count == 0 {
+            None
+        } else {
+            self.get(rng.random_range(0..self.count))
+        }
+    }
+
+    /// Returns references to `amount` distinct, randomly chosen elements (or
+    /// all elements if `amount` exceeds the length), in random order.
+    ///
+    /// # Time complexity
+    ///
+    /// O(k) where k is the number of elements chosen.
+    #[cfg(feature = "rand")]
+    pub fn choose_multiple<R: rand::Rng + ?Sized>(&self, rng: &mut R, amount: usize) -> Vec<&T> {
+        let amount = amount.min(self.count);
+        rand::seq::index::sample(rng, self.count, amount)
+            .into_iter()
+            .map(|index| &self[index])
+            .collect()
+    }
+
+    /// Removes an element from the vector and returns it.
+    ///
+    /// The removed element is replaced by the last element of the vector.
+    ///
+    /// This does not preserve ordering of the remaining elements.
+    ///
+    /// # Time complexity
+    ///
+    /// Constant time.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        if index >= self.count {
+            panic!(
+                "swap_remove index (is {index}) should be < len (is {})",
+                self.count
+            );
+        }
+        // the last element takes the place of the removed one
+        self.stats.record_len(self.count);
+        self.count -= 1;
+        let value = self.segments.swap_remove(index, self.count);
+        self.shrink();
+        value
+    }
+
+    /// Clones and appends all elements in a slice to the array.
+    ///
+    /// For `Copy` types, [`Self::extend_from_slice_copy`] will be faster.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new length exceeds the maximum capacity of the array, or
+    /// if a segment would exceed the memory budget of the array.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the length of the slice.
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.grow_to(self.count + other.len());
+        for value in other {
+            self.push(value.clone());
+        }
+    }
+
+    /// Copies all elements in a slice to the end of the array, performing a
+    /// bulk copy for each segment rather than element-wise clones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new length exceeds the maximum capacity of the array, or
+    /// if a segment would exceed the memory budget of the array.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the length of the slice.
+    pub fn extend_from_slice_copy(&mut self, other: &[T])
+    where
+        T: Copy,
+    {
+        self.grow_to(self.count + other.len());
+        self.segments.extend_copy(self.count, other);
+        self.count += other.len();
+    }
+
+    /// Clones the elements in the given range into a new array, one segment
+    /// at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, or if a segment would exceed the
+    /// memory budget shared with this array.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the range.
+    pub fn clone_range<R>(&self, range: R) -> Self
+    where
+        R: RangeBounds<usize>,
+        T: Clone,
+    {
+        let (start, end) = self.resolve_range(range);
+        let mut arr: SegmentArray<T, S> = self.new_like();
+        arr.grow_to(end - start);
+        for slice in self.range_slices(start, end) {
+            for value in slice {
+                arr.push(value.clone());
+            }
+        }
+        arr
+    }
+
+    /// Converts each element using the given function, returning a new array
+    /// with the results, or the first error returned by the function. Any
+    /// elements converted prior to the error are dropped.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn try_map<U, E, F>(&self, mut f: F) -> Result<SegmentArray<U, S>, E>
+    where
+        F: FnMut(&T) -> Result<U, E>,
+    {
+        let mut arr: SegmentArray<U, S> = self.new_like();
+        arr.grow_to(self.count);
+        for slice in self.range_slices(0, self.count) {
+            for value in slice {
+                arr.push(f(value)?);
+            }
+        }
+        Ok(arr)
+    }
+
+    /// Copies the elements in the given range into the destination slice,
+    /// using a bulk copy for each segment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, or if the length of the range
+    /// differs from the length of the destination.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the range.
+    pub fn copy_to_slice<R>(&self, range: R, dest: &mut [T])
+    where
+        R: RangeBounds<usize>,
+        T: Copy,
+    {
+        let (start, end) = self.resolve_range(range);
+        self.check_dest_len(end - start, dest.len());
+        let mut offset = 0;
+        for slice in self.range_slices(start, end) {
+            dest[offset..offset + slice.len()].copy_from_slice(slice);
+            offset += slice.len();
+        }
+    }
+
+    /// Clones the elements in the given range into the destination slice,
+    /// dropping the values that were previously in the destination.
+    ///
+    /// For `Copy` types, [`Self::copy_to_slice`] will be faster.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, or if the length of the range
+    /// differs from the length of the destination.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the range.
+    pub fn clone_to_slice<R>(&self, range: R, dest: &mut [T])
+    where
+        R: RangeBounds<usize>,
+        T: Clone,
+    {
+        let (start, end) = self.resolve_range(range);
+        self.check_dest_len(end - start, dest.len());
+        let mut offset = 0;
+        for slice in self.range_slices(start, end) {
+            dest[offset..offset + slice.len()].clone_from_slice(slice);
+            offset += slice.len();
+        }
+    }
+
+    fn check_dest_len(&self, len: usize, dest_len: usize) {
+        if len != dest_len {
+            panic!(
+                "source range length ({len}) does not match destination slice length ({dest_len})"
+            );
+        }
+    }
+
+    /// Clones all elements of the array into a new `Vec`.
+    ///
+    /// For `Copy` types, [`Self::to_vec_copy`] will be faster.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut vec = Vec::with_capacity(self.count);
+        for slice in self.range_slices(0, self.count) {
+            vec.extend_from_slice(slice);
+        }
+        vec
+    }
+
+    /// Copies all elements of the array into a new `Vec` of the exact size,
+    /// performing a bulk copy for each segment directly into the spare
+    /// capacity of the vector.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn to_vec_copy(&self) -> Vec<T>
+    where
+        T: Copy,
+    {
+        self.segments.copy_to_vec(self.count)
+    }
+
+    /// Moves all elements into a new `Vec` of the exact size, performing a
+    /// bulk copy for each segment, leaving the array empty but reusable.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn drain_to_vec(&mut self) -> Vec<T> {
+        let vec = self.segments.drain_to_vec(self.count);
+        // the values now belong to the vector, deallocate the segments
+        self.stats.record_len(self.count);
+        self.count = 0;
+        self.clear();
+        vec
+    }
+
+    /// Moves all elements into a boxed slice, which requires a single
+    /// allocation of the exact size and a bulk copy for each segment.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn into_boxed_slice(mut self) -> Box<[T]> {
+        // the vector has no excess capacity and will not be reallocated
+        self.drain_to_vec().into_boxed_slice()
+    }
+
+    /// Returns a copy of the array, performing a bulk copy for each segment
+    /// rather than element-wise clones as with `clone()`.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn clone_copy(&self) -> Self
+    where
+        T: Copy,
+    {
+        let mut arr: SegmentArray<T, S> = self.new_like();
+        for slice in self.range_slices(0, self.count) {
+            arr.extend_from_slice_copy(slice);
+        }
+        arr
+    }
+
+    /// Returns an iterator over the segment array.
+    ///
+    /// The iterator yields all items from start to end.
+    pub fn iter(&self) -> SegArrayIter<'_, T, S> {
+        SegArrayIter {
+            array: self,
+            index: 0,
+            end: self.count,
+        }
+    }
+
+    /// Returns an iterator that resumes from the position captured by
+    /// [`SegArrayIter::position_token`], without visiting the elements that
+    /// precede that position.
+    ///
+    /// If the token refers to a position beyond the end of the array, the
+    /// iterator will yield nothing.
+    pub fn iter_resume(&self, token: IterToken) -> SegArrayIter<'_, T, S> {
+        SegArrayIter {
+            array: self,
+            index: token.0.min(self.count),
+            end: self.count,
+        }
+    }
+
+    /// Returns an iterator over the runs of consecutive equal elements, each
+    /// yielded as the length of the run and a reference to its first element.
+    ///
+    /// ```
+    /// # use segment_array::SegmentArray;
+    /// let sut: SegmentArray<char> = "aaabccdd".chars().collect();
+    /// let runs: Vec<(usize, &char)> = sut.runs().collect();
+    /// assert_eq!(runs, [(3, &'a'), (1, &'b'), (2, &'c'), (2, &'d')]);
+    /// ```
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn runs(&self) -> Runs<'_, T, S>
+    where
+        T: PartialEq,
+    {
+        Runs {
+            array: self,
+            position: 0,
         }
     }
 
@@ -312,41 +1496,183 @@ impl<T> SegmentArray<T> {
     /// Note that this method has no effect on the allocated capacity of the
     /// segment array.
     pub fn clear(&mut self) {
-        if self.count > 0 && std::mem::needs_drop::<T>() {
-            // find the last segment that contains values
-            let last_segment = ((self.count >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            let last_slot = self.count - capacity_for_segment_count(last_segment);
-            unsafe {
-                std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
-                    self.segments[last_segment],
-                    last_slot,
-                ));
-            }
-            // now drop the values in all of the preceding segments
-            for segment in 0..last_segment {
-                let segment_len = slots_in_segment(segment);
-                unsafe {
-                    std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
-                        self.segments[segment],
-                        segment_len,
-                    ));
-                }
+        let len = self.count;
+        self.stats.record_len(len);
+        // the values are forgotten before being dropped such that a panicking
+        // destructor cannot lead to them being dropped again
+        self.count = 0;
+        self.drop_values(0, len, true);
+    }
+
+    /// Drops the values in the given range, which must no longer be included
+    /// in the length of the array, and optionally deallocates all segments.
+    ///
+    /// If a destructor panics, the remaining values are dropped (and the
+    /// segments deallocated) while unwinding, unless the drop policy is to
+    /// abort.
+    fn drop_values(&mut self, start: usize, end: usize, deallocate: bool) {
+        let mut guard = DropGuard {
+            array: self,
+            start,
+            end,
+            deallocate,
+        };
+        if !std::mem::needs_drop::<T>() {
+            guard.start = end;
+        }
+        if guard.array.drop_policy == DropPolicy::Abort {
+            // dropping a slice carries on with the rest of it when one of the
+            // destructors panics, so move out and drop each value in turn such
+            // that the guard aborts before any other value is dropped
+            while guard.start < guard.end {
+                let value = guard.array.segments.take(guard.start);
+                drop(value);
+                guard.start += 1;
             }
         }
+        while guard.start < guard.end {
+            let start = guard.start;
+            guard.start = segment_end(start, guard.end);
+            guard.array.segments.drop_slice(start, guard.start);
+        }
+    }
+
+    /// Moves the array to a new thread which drops the values and deallocates
+    /// the segments, returning without waiting for that to finish.
+    ///
+    /// Dropping a very large array of values that own heap memory (such as
+    /// strings) can take a long time; this keeps that cost off of the calling
+    /// thread. Any allocation hook will be invoked on the new thread. The
+    /// returned handle may be used to wait for the drop to complete, or may
+    /// simply be discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the operating system fails to create a thread.
+    pub fn drop_in_background(self) -> std::thread::JoinHandle<()>
+    where
+        T: Send + 'static,
+        S: Send + 'static,
+    {
+        std::thread::spawn(move || drop(self))
+    }
+}
+
+impl<T, S: SegmentStorage, const N: usize> SegmentArray<[T; N], S> {
+    /// Converts an array of fixed-size arrays into a flat array of `len() * N`
+    /// elements, in the same order.
+    ///
+    /// Unlike `Vec::into_flattened()` the elements must be moved, since the
+    /// size of each segment is fixed by its position in the array and thus
+    /// the segments of the flattened array are larger by a factor of `N`. The
+    /// elements are moved using a bulk copy for each segment. To view the
+    /// elements as flat slices without moving them, use
+    /// [`Self::flattened_slices`] instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the flattened length exceeds the maximum capacity, or if a
+    /// segment would exceed the memory budget of the array. The segments of
+    /// both arrays are charged to the budget until the elements have been
+    /// moved.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn into_flattened(mut self) -> SegmentArray<T, S> {
+        let mut arr: SegmentArray<T, S> = self.new_like();
+        let len = self.count.checked_mul(N).expect("capacity overflow");
+        // both sets of segments are allocated until the values are moved, and
+        // both are charged to the memory budget
+        arr.grow_to(len);
+        arr.segments
+            .extend_flattened(0, &mut self.segments, self.count);
+        arr.count = len;
+        // the values now belong to the new array, deallocate the segments and
+        // release them from the budget
         self.count = 0;
+        drop(self);
+        arr
+    }
 
-        // deallocate the segments themselves and clear everything
-        for segment in 0..self.used_segments {
-            if !self.segments[segment].is_null() {
-                let segment_len = slots_in_segment(segment);
-                let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
-                unsafe {
-                    dealloc(self.segments[segment] as *mut u8, layout);
-                }
-                self.segments[segment] = std::ptr::null_mut();
-            }
+    /// Returns the occupied portion of each segment as a flat slice, without
+    /// moving any elements.
+    pub fn flattened_slices(&self) -> impl Iterator<Item = &[T]> {
+        self.range_slices(0, self.count)
+            .map(|slice| slice.as_flattened())
+    }
+}
+
+// Number of offsets given to gather() that are sorted and prefetched at once;
+// enough to keep many loads in flight without a large buffer.
+const GATHER_BATCH_SIZE: usize = 32;
+
+// Number of elements compared by each task of the parallel comparisons; large
+// enough to amortize the scheduling overhead.
+#[cfg(feature = "rayon")]
+const PARALLEL_CHUNK_SIZE: usize = 1 << 16;
+
+#[cfg(feature = "rayon")]
+impl<T: Sync, S: SegmentStorage> SegmentArray<T, S> {
+    /// Pairs up the corresponding segments of the two arrays for the first
+    /// `len` elements; both arrays share the same segment geometry.
+    fn paired_slices<'a>(&'a self, other: &'a Self, len: usize) -> Vec<(&'a [T], &'a [T])> {
+        self.range_slices(0, len)
+            .zip(other.range_slices(0, len))
+            .collect()
+    }
+
+    /// Returns true if both arrays contain equal elements in the same order,
+    /// comparing the segments on multiple threads.
+    ///
+    /// Stops comparing as soon as any thread finds a difference.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn par_eq(&self, other: &Self) -> bool
+    where
+        T: PartialEq,
+    {
+        use rayon::prelude::*;
+        if self.count != other.count {
+            return false;
+        }
+        self.paired_slices(other, self.count)
+            .into_par_iter()
+            .flat_map(|(a, b)| {
+                a.par_chunks(PARALLEL_CHUNK_SIZE)
+                    .zip(b.par_chunks(PARALLEL_CHUNK_SIZE))
+            })
+            .all(|(a, b)| a == b)
+    }
+
+    /// Compares the two arrays lexicographically, comparing the segments on
+    /// multiple threads.
+    ///
+    /// Stops comparing once the first difference has been found.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn par_cmp(&self, other: &Self) -> Ordering
+    where
+        T: Ord,
+    {
+        use rayon::prelude::*;
+        let len = self.count.min(other.count);
+        let mismatch = self
+            .paired_slices(other, len)
+            .into_par_iter()
+            .flat_map(|(a, b)| {
+                a.par_chunks(PARALLEL_CHUNK_SIZE)
+                    .zip(b.par_chunks(PARALLEL_CHUNK_SIZE))
+            })
+            .find_first(|(a, b)| a != b);
+        match mismatch {
+            Some((a, b)) => a.cmp(b),
+            None => self.count.cmp(&other.count),
         }
-        self.used_segments = 0;
     }
 }
 
@@ -356,28 +1682,143 @@ impl<T> Default for SegmentArray<T> {
     }
 }
 
-impl<T> fmt::Display for SegmentArray<T> {
+impl<T: Clone, S: SegmentStorage> Clone for SegmentArray<T, S> {
+    /// Clones the array, sharing its memory budget (if any).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a segment would exceed the memory budget shared with this
+    /// array.
+    fn clone(&self) -> Self {
+        let mut arr: SegmentArray<T, S> = self.new_like();
+        arr.grow_to(self.count);
+        for slice in self.range_slices(0, self.count) {
+            for value in slice {
+                arr.push(value.clone());
+            }
+        }
+        arr
+    }
+}
+
+impl<T> From<Vec<T>> for SegmentArray<T> {
+    /// Moves the elements of the vector into a new segment array using a bulk
+    /// copy for each segment.
+    fn from(vec: Vec<T>) -> Self {
+        let mut arr: SegmentArray<T> = SegmentArray::new();
+        let len = vec.len();
+        arr.grow_to(len);
+        arr.segments.extend_vec(0, vec);
+        arr.count = len;
+        arr
+    }
+}
+
+impl<T, S: SegmentStorage> fmt::Display for SegmentArray<T, S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let longest_segment = if self.used_segments > 0 {
-            slots_in_segment(self.used_segments - 1)
+        let longest_segment = if self.segments.used() > 0 {
+            slots_in_segment(self.segments.used() - 1)
         } else {
             0
         };
         write!(
             f,
             "SegmentArray(count: {}, used_segments: {}, longest segment: {})",
-            self.count, self.used_segments, longest_segment
+            self.count,
+            self.segments.used(),
+            longest_segment
         )
     }
 }
 
-impl<T> Drop for SegmentArray<T> {
+// Number of elements included when formatting an array with defmt.
+#[cfg(feature = "defmt")]
+const DEFMT_PREVIEW_LEN: usize = 8;
+
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format, S: SegmentStorage> defmt::Format for SegmentArray<T, S> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "SegmentArray(count: {=usize}, [", self.count);
+        for (index, value) in self.iter().take(DEFMT_PREVIEW_LEN).enumerate() {
+            if index > 0 {
+                defmt::write!(fmt, ", ");
+            }
+            defmt::write!(fmt, "{}", value);
+        }
+        if self.count > DEFMT_PREVIEW_LEN {
+            defmt::write!(fmt, ", ...");
+        }
+        defmt::write!(fmt, "])");
+    }
+}
+
+// Reports the allocated segments, including any unused capacity, plus the
+// memory owned by each of the elements.
+#[cfg(feature = "deepsize")]
+impl<T: deepsize::DeepSizeOf, S: SegmentStorage> deepsize::DeepSizeOf for SegmentArray<T, S> {
+    fn deep_size_of_children(&self, context: &mut deepsize::Context) -> usize {
+        self.iter()
+            .map(|value| value.deep_size_of_children(context))
+            .sum::<usize>()
+            + self.capacity() * size_of::<T>()
+    }
+}
+
+#[cfg(feature = "get-size")]
+impl<T: get_size::GetSize, S: SegmentStorage> get_size::GetSize for SegmentArray<T, S> {
+    fn get_heap_size(&self) -> usize {
+        self.iter()
+            .map(get_size::GetSize::get_heap_size)
+            .sum::<usize>()
+            + self.capacity() * size_of::<T>()
+    }
+}
+
+impl<T, S: SegmentStorage> Drop for SegmentArray<T, S> {
     fn drop(&mut self) {
         self.clear();
     }
 }
 
-impl<T> Index<usize> for SegmentArray<T> {
+// Returns the end of the portion of the given range that lies within the
+// segment containing the start of the range.
+fn segment_end(start: usize, end: usize) -> usize {
+    let (segment, slot) = locate(start);
+    end.min(start + slots_in_segment(segment) - slot)
+}
+
+// Finishes dropping the values and deallocating the segments of an array,
+// including when unwinding from a panicking destructor.
+struct DropGuard<'a, T, S: SegmentStorage> {
+    array: &'a mut SegmentArray<T, S>,
+    // offset of the next value to be dropped
+    start: usize,
+    end: usize,
+    deallocate: bool,
+}
+
+impl<T, S: SegmentStorage> Drop for DropGuard<'_, T, S> {
+    fn drop(&mut self) {
+        if self.start < self.end {
+            // a destructor panicked, any further panic will abort
+            if self.array.drop_policy == DropPolicy::Abort {
+                std::process::abort();
+            }
+            while self.start < self.end {
+                let start = self.start;
+                self.start = segment_end(start, self.end);
+                self.array.segments.drop_slice(start, self.start);
+            }
+        }
+        if self.deallocate {
+            while self.array.segments.used() > 0 {
+                self.array.deallocate_segment();
+            }
+        }
+    }
+}
+
+impl<T, S: SegmentStorage> Index<usize> for SegmentArray<T, S> {
     type Output = T;
 
     fn index(&self, index: usize) -> &Self::Output {
@@ -388,7 +1829,7 @@ impl<T> Index<usize> for SegmentArray<T> {
     }
 }
 
-impl<T> IndexMut<usize> for SegmentArray<T> {
+impl<T, S: SegmentStorage> IndexMut<usize> for SegmentArray<T, S> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         let Some(item) = self.get_mut(index) else {
             panic!("index out of bounds: {}", index);
@@ -403,185 +1844,738 @@ impl<A> FromIterator<A> for SegmentArray<A> {
         for value in iter {
             arr.push(value)
         }
-        arr
+        arr
+    }
+}
+
+/// Immutable segment array iterator.
+pub struct SegArrayIter<'a, T, S: SegmentStorage = Heap> {
+    array: &'a SegmentArray<T, S>,
+    index: usize,
+    // offset at which iteration stops, at most the length of the array
+    end: usize,
+}
+
+impl<'a, T, S: SegmentStorage> Iterator for SegArrayIter<'a, T, S> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index < self.end {
+            let value = self.array.segments.get(self.index);
+            self.index += 1;
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, S: SegmentStorage> ExactSizeIterator for SegArrayIter<'_, T, S> {}
+
+impl<T, S: SegmentStorage> FusedIterator for SegArrayIter<'_, T, S> {}
+
+// size_hint() is always exact
+#[cfg(all(feature = "nightly", not(segment_array_safe)))]
+unsafe impl<T, S: SegmentStorage> std::iter::TrustedLen for SegArrayIter<'_, T, S> {}
+
+impl<T, S: SegmentStorage> SegArrayIter<'_, T, S> {
+    /// Returns a token for the position of the next element to be yielded by
+    /// this iterator, which can be given to [`SegmentArray::iter_resume`] to
+    /// continue iterating from the same position later.
+    pub fn position_token(&self) -> IterToken {
+        IterToken(self.index)
+    }
+}
+
+///
+/// Iterator over the runs of consecutive equal elements, as returned by
+/// [`SegmentArray::runs`].
+///
+pub struct Runs<'a, T, S: SegmentStorage = Heap> {
+    array: &'a SegmentArray<T, S>,
+    // offset of the first element of the next run
+    position: usize,
+}
+
+impl<'a, T: PartialEq, S: SegmentStorage> Iterator for Runs<'a, T, S> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let array = self.array;
+        let mut slices = array.range_slices(self.position, array.count);
+        let first = slices.next()?;
+        let value = &first[0];
+        // the first element always begins the run, even if it is not equal to
+        // itself (e.g. NaN), so that the iterator makes progress
+        let mut count = 1;
+        for slice in std::iter::once(&first[1..]).chain(slices) {
+            let run = slice
+                .iter()
+                .position(|other| other != value)
+                .unwrap_or(slice.len());
+            count += run;
+            if run < slice.len() {
+                break;
+            }
+        }
+        self.position += count;
+        Some((count, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.array.count - self.position;
+        (remaining.min(1), Some(remaining))
+    }
+}
+
+impl<T: PartialEq, S: SegmentStorage> FusedIterator for Runs<'_, T, S> {}
+
+///
+/// Position within a segment array at which iteration can be resumed. The
+/// token is simply an offset and can be saved and restored via
+/// [`IterToken::index`] and [`IterToken::from_index`].
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IterToken(usize);
+
+impl IterToken {
+    /// Return a token for the given offset within an array.
+    pub fn from_index(index: usize) -> Self {
+        Self(index)
+    }
+
+    /// Returns the offset of the next element to be visited.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// An iterator that moves out of a segment array.
+pub struct SegArrayIntoIter<T, S: SegmentStorage = Heap> {
+    index: usize,
+    array: SegmentArray<T, S>,
+}
+
+impl<T, S: SegmentStorage> Iterator for SegArrayIntoIter<T, S> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index < self.array.count {
+            let value = self.array.segments.take(self.index);
+            self.index += 1;
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.array.count - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, S: SegmentStorage> ExactSizeIterator for SegArrayIntoIter<T, S> {}
+
+impl<T, S: SegmentStorage> FusedIterator for SegArrayIntoIter<T, S> {}
+
+// size_hint() is always exact
+#[cfg(all(feature = "nightly", not(segment_array_safe)))]
+unsafe impl<T, S: SegmentStorage> std::iter::TrustedLen for SegArrayIntoIter<T, S> {}
+
+impl<T, S: SegmentStorage> Drop for SegArrayIntoIter<T, S> {
+    fn drop(&mut self) {
+        // drop the values that have not yet been visited, forgetting them
+        // first in case a destructor panics
+        let count = self.array.count;
+        self.array.count = 0;
+        self.array.drop_values(self.index, count, false);
+
+        // the array deallocates the segments when it is dropped
+        self.index = 0;
+    }
+}
+
+impl<T, S: SegmentStorage> IntoIterator for SegmentArray<T, S> {
+    type Item = T;
+    type IntoIter = SegArrayIntoIter<T, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SegArrayIntoIter {
+            index: 0,
+            array: self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_static() {
+        static ARRAY: std::sync::Mutex<SegmentArray<String>> =
+            std::sync::Mutex::new(SegmentArray::new());
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    for value in 0..100 {
+                        ARRAY.lock().unwrap().push(format!("{t}-{value}"));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut array = ARRAY.lock().unwrap();
+        assert_eq!(array.len(), 400);
+        array.clear();
+        assert_eq!(array.capacity(), 0);
+    }
+
+    #[test]
+    fn test_push_within_capacity() {
+        // empty array has no allocated space
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        assert_eq!(sut.push_within_capacity(101), Err(101));
+        sut.push(10);
+        assert_eq!(sut.push_within_capacity(101), Ok(()));
+
+        // edge case (first segment is 64 elements long)
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        for value in 1..64 {
+            sut.push(value);
+        }
+        assert_eq!(sut.push_within_capacity(64), Ok(()));
+        assert_eq!(sut.push_within_capacity(65), Err(65));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        sut.reserve(0);
+        assert_eq!(sut.capacity(), 0);
+        sut.reserve(1);
+        assert_eq!(sut.capacity(), 64);
+        sut.reserve(64);
+        assert_eq!(sut.capacity(), 64);
+        sut.reserve(65);
+        assert_eq!(sut.capacity(), 192);
+        sut.push(1);
+        sut.reserve(191);
+        assert_eq!(sut.capacity(), 192);
+        sut.reserve(192);
+        assert_eq!(sut.capacity(), 448);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_reserve_overflow() {
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        sut.push(1);
+        sut.reserve(usize::MAX);
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        assert_eq!(sut.try_reserve(100), Ok(()));
+        assert_eq!(sut.capacity(), 192);
+        assert_eq!(sut.try_push(1), Ok(()));
+        assert_eq!(
+            sut.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        let max = capacity_for_segment_count(MAX_SEGMENT_COUNT);
+        assert_eq!(sut.try_reserve(max), Err(TryReserveError::CapacityOverflow));
+        assert_eq!(sut.capacity(), 192);
+        assert_eq!(sut[0], 1);
+    }
+
+    #[cfg(feature = "test-support")]
+    #[test]
+    fn test_try_reserve_alloc_error() {
+        let mut sut: SegmentArray<u32, _> = SegmentArray::new_in(FailingStorage::new(3));
+        assert_eq!(sut.try_reserve(100), Ok(()));
+        let err = sut.try_reserve(500).unwrap_err();
+        assert_eq!(
+            err,
+            TryReserveError::AllocError {
+                layout: Layout::array::<u32>(256).unwrap()
+            }
+        );
+        assert_eq!(err.to_string(), "memory allocation of 1024 bytes failed");
+        // only the third allocation fails
+        assert_eq!(sut.capacity(), 192);
+        assert_eq!(sut.try_reserve(500), Ok(()));
+        assert_eq!(sut.capacity(), 960);
+        for value in 0..960 {
+            assert_eq!(sut.try_push(value), Ok(()));
+        }
+        let mut sut: SegmentArray<u32, _> = SegmentArray::new_in(FailingStorage::new(2));
+        for value in 0..64 {
+            sut.try_push(value).unwrap();
+        }
+        assert!(sut.try_push(64).is_err());
+        assert_eq!(sut.len(), 64);
+    }
+
+    #[cfg(feature = "test-support")]
+    #[test]
+    fn test_failed_clone_drops_values() {
+        // an allocation failure part way through a clone unwinds, dropping
+        // the values that were cloned
+        let marker = std::rc::Rc::new(());
+        let mut sut: SegmentArray<std::rc::Rc<()>, _> =
+            SegmentArray::new_in(FailingStorage::new(4));
+        for _ in 0..100 {
+            sut.push(marker.clone());
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sut.clone()));
+        let err = result.err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<String>().unwrap(),
+            "memory allocation of 1024 bytes failed"
+        );
+        assert_eq!(std::rc::Rc::strong_count(&marker), 101);
+        drop(sut);
+        assert_eq!(std::rc::Rc::strong_count(&marker), 1);
+    }
+
+    // Counts drops of a shared marker, panicking when dropped if asked to.
+    struct PanicOnDrop(std::rc::Rc<std::cell::Cell<usize>>, bool);
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+            if self.1 {
+                panic!("dropping a poisoned value");
+            }
+        }
+    }
+
+    #[test]
+    fn test_clear_panicking_destructor() {
+        let drops = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut sut: SegmentArray<PanicOnDrop> = SegmentArray::new();
+        for value in 0..1000 {
+            sut.push(PanicOnDrop(drops.clone(), value == 10));
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sut.clear()));
+        assert!(result.is_err());
+        // every value was dropped exactly once and the segments released
+        assert_eq!(drops.get(), 1000);
+        assert_eq!(sut.len(), 0);
+        assert_eq!(sut.capacity(), 0);
+        drop(sut);
+        assert_eq!(drops.get(), 1000);
+
+        // the same when the panic escapes from drop()
+        drops.set(0);
+        let mut sut: SegmentArray<PanicOnDrop> = SegmentArray::new();
+        for value in 0..1000 {
+            sut.push(PanicOnDrop(drops.clone(), value == 500));
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(sut)));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 1000);
+    }
+
+    #[test]
+    fn test_into_iter_panicking_destructor() {
+        let drops = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut sut: SegmentArray<PanicOnDrop> = SegmentArray::new();
+        for value in 0..300 {
+            sut.push(PanicOnDrop(drops.clone(), value == 100));
+        }
+        let mut iter = sut.into_iter();
+        for _ in 0..50 {
+            iter.next();
+        }
+        assert_eq!(drops.get(), 50);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(iter)));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 300);
+    }
+
+    #[test]
+    fn test_drop_policy_abort() {
+        // the process is aborted, hence the array is dropped by a child process
+        // that runs only this test
+        if std::env::var_os("SEGMENT_ARRAY_ABORT_TEST").is_some() {
+            struct Noisy(usize);
+
+            impl Drop for Noisy {
+                fn drop(&mut self) {
+                    println!("dropped {}", self.0);
+                    if self.0 == 10 {
+                        panic!("dropping a poisoned value");
+                    }
+                }
+            }
+
+            let mut sut: SegmentArray<Noisy> = (0..100).map(Noisy).collect();
+            sut.set_drop_policy(DropPolicy::Abort);
+            drop(sut);
+            unreachable!("the process should have been aborted");
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::test_drop_policy_abort", "--nocapture"])
+            .env("SEGMENT_ARRAY_ABORT_TEST", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // the first line follows the name of the test on the same line
+        let dropped: Vec<&str> = stdout
+            .lines()
+            .filter_map(|line| line.find("dropped ").map(|pos| &line[pos..]))
+            .collect();
+        let expected: Vec<String> = (0..=10).map(|value| format!("dropped {value}")).collect();
+        assert_eq!(dropped, expected);
+    }
+
+    #[test]
+    fn test_drop_policy() {
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        assert_eq!(sut.drop_policy(), DropPolicy::Unwind);
+        sut.set_drop_policy(DropPolicy::Abort);
+        sut.push(1);
+        assert_eq!(sut.clone().drop_policy(), DropPolicy::Abort);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let budget = MemoryBudget::new(448 * 4);
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        sut.extend_from_slice_copy(&[1; 100]);
+        sut.set_memory_budget(budget.clone()).unwrap();
+        assert_eq!(budget.used(), 192 * 4);
+        for value in 0..348 {
+            sut.try_push(value).unwrap();
+        }
+        assert_eq!(budget.remaining(), 0);
+        let err = sut.try_push(1).unwrap_err();
+        assert_eq!(
+            err,
+            TryReserveError::BudgetExceeded {
+                requested: 512 * 4,
+                remaining: 0
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "memory budget exceeded: requested 2048 bytes with 0 remaining"
+        );
+        // clones share the budget
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sut.clone()));
+        assert!(result.is_err());
+        assert_eq!(budget.used(), 448 * 4);
+        let mut other: SegmentArray<u32> = SegmentArray::new();
+        other.push(1);
+        assert!(other.set_memory_budget(budget.clone()).is_err());
+        assert!(other.memory_budget().is_none());
+        // deallocation returns the bytes to the budget
+        for _ in 0..300 {
+            sut.pop();
+        }
+        assert_eq!(budget.used(), 192 * 4);
+        assert!(other.set_memory_budget(budget.clone()).is_ok());
+        assert_eq!(budget.used(), 256 * 4);
+        other.clear_memory_budget();
+        drop(sut);
+        assert_eq!(budget.used(), 0);
+    }
+
+    // the functions are safe when built with segment_array_safe
+    #[cfg(not(segment_array_safe))]
+    #[test]
+    fn test_push_pop_unchecked() {
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        sut.reserve(1000);
+        for value in 0..1000 {
+            unsafe { sut.push_unchecked(value.to_string()) };
+        }
+        assert_eq!(sut.len(), 1000);
+        for (idx, elem) in sut.iter().enumerate() {
+            assert_eq!(idx.to_string(), *elem);
+        }
+        for value in (0..1000).rev() {
+            assert_eq!(unsafe { sut.pop_unchecked() }, value.to_string());
+        }
+        assert!(sut.is_empty());
+    }
+
+    #[test]
+    fn test_prepare_next_segment() {
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        assert!(sut.prepare_next_segment());
+        assert_eq!(sut.capacity(), 64);
+        assert!(!sut.prepare_next_segment());
+        for value in 0..64 {
+            sut.push(value);
+        }
+        assert!(sut.prepare_next_segment());
+        assert_eq!(sut.capacity(), 192);
+        assert!(!sut.prepare_next_segment());
+        sut.push(64);
+        assert_eq!(sut.capacity(), 192);
+        for (idx, elem) in sut.iter().enumerate() {
+            assert_eq!(idx, *elem as usize);
+        }
     }
-}
-
-/// Immutable segment array iterator.
-pub struct SegArrayIter<'a, T> {
-    array: &'a SegmentArray<T>,
-    index: usize,
-}
 
-impl<'a, T> Iterator for SegArrayIter<'a, T> {
-    type Item = &'a T;
+    #[test]
+    fn test_install_segment() {
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        for value in 0..64 {
+            sut.push(value.to_string());
+        }
+        let next = sut.next_segment();
+        assert_eq!(next, 1);
+        let handle = std::thread::spawn(move || PreparedSegment::<String>::new(next));
+        let prepared = handle.join().unwrap();
+        assert_eq!(prepared.segment(), 1);
+        assert!(sut.install_segment(prepared));
+        assert_eq!(sut.capacity(), 192);
+        for value in 64..192 {
+            sut.push(value.to_string());
+        }
+        assert_eq!(sut.capacity(), 192);
+        assert_eq!(sut[191], "191");
+        // a segment that no longer matches is rejected
+        assert!(!sut.install_segment(PreparedSegment::new(1)));
+        assert_eq!(sut.capacity(), 192);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let value = self.array.get(self.index);
-        self.index += 1;
-        value
+    #[test]
+    fn test_with_zeroed() {
+        let sut: SegmentArray<u64> = SegmentArray::with_zeroed(0);
+        assert!(sut.is_empty());
+        assert_eq!(sut.capacity(), 0);
+        let mut sut: SegmentArray<u64> = SegmentArray::with_zeroed(1000);
+        assert_eq!(sut.len(), 1000);
+        assert_eq!(sut.capacity(), 1984);
+        assert!(sut.iter().all(|v| *v == 0));
+        sut.push(1);
+        assert_eq!(sut[1000], 1);
+        assert_eq!(sut.pop(), Some(1));
+        assert_eq!(sut.pop(), Some(0));
+        let sut: SegmentArray<[f32; 4]> = SegmentArray::with_zeroed(64);
+        assert_eq!(sut.len(), 64);
+        assert_eq!(sut.capacity(), 64);
+        assert_eq!(sut[63], [0.0; 4]);
     }
-}
 
-/// An iterator that moves out of a segment array.
-pub struct SegArrayIntoIter<T> {
-    index: usize,
-    count: usize,
-    used_segments: usize,
-    segments: [*mut T; MAX_SEGMENT_COUNT],
-}
+    // Subscriber that records the message of every event.
+    #[cfg(feature = "tracing")]
+    struct EventRecorder(Arc<std::sync::Mutex<Vec<String>>>);
 
-impl<T> Iterator for SegArrayIntoIter<T> {
-    type Item = T;
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for EventRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.count {
-            let segment = ((self.index >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            let slot = self.index - capacity_for_segment_count(segment);
-            self.index += 1;
-            unsafe { Some((self.segments[segment].add(slot)).read()) }
-        } else {
-            None
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
         }
-    }
-}
 
-impl<T> Drop for SegArrayIntoIter<T> {
-    fn drop(&mut self) {
-        if self.count > 0 && std::mem::needs_drop::<T>() {
-            let first_segment = ((self.index >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            let last_segment = (((self.count - 1) >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
-            if first_segment == last_segment {
-                // special-case, remaining values are in only one segment
-                let first = self.index - capacity_for_segment_count(first_segment);
-                let last = self.count - capacity_for_segment_count(first_segment);
-                if first < last {
-                    let len = last - first;
-                    unsafe {
-                        let first: *mut T = self.segments[first_segment].add(first);
-                        std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(first, len));
-                    }
-                }
-            } else if first_segment < last_segment {
-                // drop values in the first segment that still has values to be
-                // visited
-                let first = self.index - capacity_for_segment_count(first_segment);
-                let segment_len = slots_in_segment(first_segment);
-                if segment_len < self.count {
-                    unsafe {
-                        let ptr: *mut T = self.segments[first_segment].add(first);
-                        let len = segment_len - first;
-                        std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(ptr, len));
-                    }
-                }
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
 
-                // drop the values in the last segment
-                let last_slot = self.count - capacity_for_segment_count(last_segment);
-                unsafe {
-                    std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
-                        self.segments[last_segment],
-                        last_slot,
-                    ));
-                }
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
 
-                // now drop the values in all of the other segments
-                for segment in first_segment + 1..last_segment {
-                    let segment_len = slots_in_segment(segment);
-                    unsafe {
-                        std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
-                            self.segments[segment],
-                            segment_len,
-                        ));
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Message<'a>(&'a mut Vec<String>);
+            impl tracing::field::Visit for Message<'_> {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0.push(format!("{value:?}"));
                     }
                 }
             }
+            event.record(&mut Message(&mut self.0.lock().unwrap()));
         }
 
-        // deallocate the segments themselves and clear everything
-        for segment in 0..self.used_segments {
-            if !self.segments[segment].is_null() {
-                let segment_len = slots_in_segment(segment);
-                let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
-                unsafe {
-                    dealloc(self.segments[segment] as *mut u8, layout);
-                }
-                self.segments[segment] = std::ptr::null_mut();
-            }
-        }
-        self.index = 0;
-        self.count = 0;
-        self.used_segments = 0;
-    }
-}
-
-impl<T> IntoIterator for SegmentArray<T> {
-    type Item = T;
-    type IntoIter = SegArrayIntoIter<Self::Item>;
+        fn enter(&self, _: &tracing::span::Id) {}
 
-    fn into_iter(self) -> Self::IntoIter {
-        let me = std::mem::ManuallyDrop::new(self);
-        SegArrayIntoIter {
-            index: 0,
-            count: me.count,
-            used_segments: me.used_segments,
-            segments: me.segments,
-        }
+        fn exit(&self, _: &tracing::span::Id) {}
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_trace_threshold() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = EventRecorder(events.clone());
+        tracing::subscriber::with_default(recorder, || {
+            let mut sut: SegmentArray<u64> = SegmentArray::new();
+            sut.set_trace_threshold(Some(4096));
+            for value in 0..1000 {
+                sut.push(value);
+            }
+            assert_eq!(sut.len(), 1000);
+            sut.clear();
+            assert_eq!(sut.capacity(), 0);
+        });
+        // the fourth segment brings the total to 7,680 bytes
+        let allocated = "segment allocated";
+        let deallocated = "segment deallocated";
+        let exceeded = "segment array exceeded allocation threshold";
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                allocated,
+                allocated,
+                allocated,
+                allocated,
+                exceeded,
+                allocated,
+                deallocated,
+                deallocated,
+                deallocated,
+                deallocated,
+                deallocated,
+            ]
+        );
+    }
 
+    #[cfg(feature = "tracing")]
     #[test]
-    fn test_slots_in_segment() {
-        // values are simply capacity_for_segment_count() plus 64 but there
-        // should be a test for this function regardless of its simplicity
-        let expected_values = [
-            64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536, 131072, 262144, 524288,
-            1048576, 2097152, 4194304, 8388608, 16777216, 33554432, 67108864, 134217728, 268435456,
-            536870912, 1073741824, 2147483648,
-        ];
-        assert_eq!(expected_values.len(), MAX_SEGMENT_COUNT);
-        for (segment, item) in expected_values.iter().enumerate() {
-            assert_eq!(*item, slots_in_segment(segment));
-        }
+    fn test_trace_install_segment() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = EventRecorder(events.clone());
+        tracing::subscriber::with_default(recorder, || {
+            let mut sut: SegmentArray<u64> = SegmentArray::new();
+            let prepared = PreparedSegment::new(sut.next_segment());
+            assert!(sut.install_segment(prepared));
+        });
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "segment prepared",
+                "segment allocated",
+                "segment deallocated"
+            ]
+        );
     }
 
     #[test]
-    fn test_capacity_for_segment_count() {
-        //
-        // from https://danielchasehooper.com/posts/segment_array/segment_array.h:
-        //
-        // 26 segments with 6 skipped segments can hold 4,294,967,232 items, aka
-        // capacity_for_segment_count(26)
-        //
-        let expected_values = [
-            0, 64, 192, 448, 960, 1984, 4032, 8128, 16320, 32704, 65472, 131008, 262080, 524224,
-            1048512, 2097088, 4194240, 8388544, 16777152, 33554368, 67108800, 134217664, 268435392,
-            536870848, 1073741760, 2147483584, 4294967232,
-        ];
-        assert_eq!(expected_values.len(), MAX_SEGMENT_COUNT + 1);
-        for (count, item) in expected_values.iter().enumerate() {
-            assert_eq!(*item, capacity_for_segment_count(count));
+    fn test_allocation_hook() {
+        use std::sync::Mutex;
+        let events: Arc<Mutex<Vec<SegmentEvent>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        let recorder = events.clone();
+        sut.set_allocation_hook(move |event| recorder.lock().unwrap().push(*event));
+        for value in 0..100 {
+            sut.push(value);
+        }
+        let copy = sut.clone();
+        drop(copy);
+        while sut.len() > 10 {
+            sut.pop();
         }
+        sut.clear_allocation_hook();
+        sut.clear();
+        let layout_0 = Layout::array::<u32>(64).unwrap();
+        let layout_1 = Layout::array::<u32>(128).unwrap();
+        let actual = events.lock().unwrap().clone();
+        assert_eq!(
+            actual,
+            vec![
+                SegmentEvent::Allocated {
+                    segment: 0,
+                    layout: layout_0
+                },
+                SegmentEvent::Allocated {
+                    segment: 1,
+                    layout: layout_1
+                },
+                // the clone shares the hook
+                SegmentEvent::Allocated {
+                    segment: 0,
+                    layout: layout_0
+                },
+                SegmentEvent::Allocated {
+                    segment: 1,
+                    layout: layout_1
+                },
+                SegmentEvent::Deallocated {
+                    segment: 1,
+                    layout: layout_1
+                },
+                SegmentEvent::Deallocated {
+                    segment: 0,
+                    layout: layout_0
+                },
+                // popping elements deallocates the second segment
+                SegmentEvent::Deallocated {
+                    segment: 1,
+                    layout: layout_1
+                },
+            ]
+        );
     }
 
     #[test]
-    fn test_push_within_capacity() {
-        // empty array has no allocated space
+    fn test_lifetime_stats() {
         let mut sut: SegmentArray<u32> = SegmentArray::new();
-        assert_eq!(sut.push_within_capacity(101), Err(101));
-        sut.push(10);
-        assert_eq!(sut.push_within_capacity(101), Ok(()));
+        assert_eq!(sut.stats(), LifetimeStats::default());
+        for value in 0..200 {
+            sut.push(value);
+        }
+        while sut.len() > 50 {
+            sut.pop();
+        }
+        sut.swap_remove(0);
+        let stats = sut.stats();
+        assert_eq!(stats.peak_len, 200);
+        assert_eq!(stats.segments_allocated, 3);
+        assert_eq!(stats.bytes_allocated, 448 * 4);
+        for value in 0..100 {
+            sut.push(value);
+        }
+        let stats = sut.stats();
+        assert_eq!(stats.peak_len, 200);
+        assert_eq!(stats.segments_allocated, 4);
+        assert_eq!(stats.bytes_allocated, (448 + 128) * 4);
+        sut.clear();
+        assert_eq!(sut.stats().peak_len, 200);
+        sut.reset_stats();
+        assert_eq!(sut.stats(), LifetimeStats::default());
+        sut.push(1);
+        assert_eq!(sut.stats().peak_len, 1);
+        assert_eq!(sut.stats().segments_allocated, 1);
+    }
 
-        // edge case (first segment is 64 elements long)
+    // reading the poisoned slots is an error when using AddressSanitizer
+    #[cfg(all(feature = "poison", not(feature = "asan"), not(segment_array_safe)))]
+    #[test]
+    fn test_poison_slots() {
         let mut sut: SegmentArray<u32> = SegmentArray::new();
-        for value in 1..64 {
+        for value in 0..100 {
             sut.push(value);
         }
-        assert_eq!(sut.push_within_capacity(64), Ok(()));
-        assert_eq!(sut.push_within_capacity(65), Err(65));
+        let read_slot =
+            |sut: &SegmentArray<u32>, index: usize| unsafe { sut.segments.raw_slot(index).read() };
+        // never-initialized capacity
+        assert_eq!(read_slot(&sut, 100), 0xCDCDCDCD);
+        assert_eq!(read_slot(&sut, 191), 0xCDCDCDCD);
+        // removed values
+        sut.pop();
+        assert_eq!(read_slot(&sut, 99), 0xDDDDDDDD);
+        assert_eq!(sut.swap_remove(10), 10);
+        assert_eq!(sut[10], 98);
+        assert_eq!(read_slot(&sut, 98), 0xDDDDDDDD);
+        let sut: SegmentArray<u32> = SegmentArray::with_zeroed(10);
+        assert_eq!(read_slot(&sut, 9), 0);
+        assert_eq!(read_slot(&sut, 10), 0xCDCDCDCD);
+        assert_eq!(read_slot(&sut, 63), 0xCDCDCDCD);
     }
 
     #[test]
@@ -638,6 +2632,105 @@ mod tests {
         assert_eq!(sut[2], "third planet");
     }
 
+    #[test]
+    fn test_get_many() {
+        let sut: SegmentArray<String> = (0..1000).map(|v| v.to_string()).collect();
+        let [a, b, c] = sut.get_many([999, 0, 999]).unwrap();
+        assert_eq!(a, "999");
+        assert_eq!(b, "0");
+        assert_eq!(c, "999");
+        assert!(sut.get_many([1, 1000]).is_none());
+        assert_eq!(sut.get_many([]), Some([]));
+        // offsets scattered over many segments come back in the given order
+        let indices: [usize; 40] = std::array::from_fn(|i| (i * 577) % 1000);
+        let actual = sut.get_many(indices).unwrap();
+        for (value, index) in actual.iter().zip(indices.iter()) {
+            assert_eq!(*value, &index.to_string());
+        }
+    }
+
+    #[test]
+    fn test_gather() {
+        let sut: SegmentArray<usize> = (0..1000).collect();
+        let actual: Vec<Option<&usize>> = sut.gather([500, 1000, 3]).collect();
+        assert_eq!(actual, vec![Some(&500), None, Some(&3)]);
+        let indices: Vec<usize> = (0..1000).rev().collect();
+        for (value, index) in sut.gather(indices.iter().copied()).zip(indices.iter()) {
+            assert_eq!(value, Some(index));
+        }
+        // scattered offsets, some out of bounds, spanning several batches
+        let indices: Vec<usize> = (0..200).map(|i| (i * 7919) % 1100).collect();
+        let actual: Vec<Option<&usize>> = sut.gather(indices.iter().copied()).collect();
+        assert_eq!(actual.len(), indices.len());
+        for (value, index) in actual.into_iter().zip(indices.iter()) {
+            if *index < 1000 {
+                assert_eq!(value, Some(index));
+            } else {
+                assert_eq!(value, None);
+            }
+        }
+        assert_eq!(sut.gather(std::iter::empty()).count(), 0);
+    }
+
+    #[test]
+    fn test_exponential_partition_point() {
+        let inputs: Vec<u32> = (0..3000).map(|v| (v / 4) * 3).collect();
+        let sut: SegmentArray<u32> = inputs.iter().copied().collect();
+        for key in [0, 1, 3, 700, 1500, 2247, 2248, 5000] {
+            let expected = inputs.partition_point(|v| *v < key);
+            for hint in [0, 1, 63, 64, 500, 1000, 2999, 3000, usize::MAX] {
+                let actual = sut.exponential_partition_point(hint, |v| *v < key);
+                assert_eq!(actual, expected, "key {key} hint {hint}");
+            }
+        }
+        let empty: SegmentArray<u32> = SegmentArray::new();
+        assert_eq!(empty.exponential_partition_point(5, |v| *v < 1), 0);
+    }
+
+    #[test]
+    fn test_exponential_search() {
+        let sut: SegmentArray<u32> = (0..1000).map(|v| v * 2).collect();
+        assert_eq!(sut.exponential_search(&500, 990), Ok(250));
+        assert_eq!(sut.exponential_search(&501, 0), Err(251));
+        assert_eq!(sut.exponential_search(&1998, 999), Ok(999));
+        assert_eq!(sut.exponential_search(&5000, 999), Err(1000));
+        assert_eq!(sut.exponential_search(&0, 999), Ok(0));
+        let dupes: SegmentArray<u32> = [1, 2, 2, 2, 2, 3].into_iter().collect();
+        assert_eq!(dupes.exponential_search(&2, 5), Ok(1));
+    }
+
+    #[test]
+    fn test_find() {
+        let inputs: Vec<u32> = (0..2000).map(|v| v % 97).collect();
+        let sut: SegmentArray<u32> = inputs.iter().copied().collect();
+        for (start, len) in [
+            (0, 1),
+            (10, 5),
+            (60, 8),
+            (63, 2),
+            (190, 100),
+            (0, 97),
+            (900, 97),
+        ] {
+            let needle = &inputs[start..start + len];
+            let expected = inputs.windows(len).position(|w| w == needle);
+            assert_eq!(sut.find(needle), expected, "start {start} len {len}");
+        }
+        assert_eq!(sut.find(&[]), Some(0));
+        assert_eq!(sut.find(&[96, 96]), None);
+        assert_eq!(sut.find(&inputs), Some(0));
+        let mut longer = inputs.clone();
+        longer.push(0);
+        assert_eq!(sut.find(&longer), None);
+        let empty: SegmentArray<u32> = SegmentArray::new();
+        assert_eq!(empty.find(&[1]), None);
+        // needle spans a segment boundary only
+        let mut sut: SegmentArray<u32> = (0..200).map(|_| 0).collect();
+        sut[63] = 7;
+        sut[64] = 8;
+        assert_eq!(sut.find(&[7, 8]), Some(63));
+    }
+
     #[test]
     #[should_panic(expected = "index out of bounds:")]
     fn test_index_out_of_bounds() {
@@ -773,6 +2866,93 @@ mod tests {
         sut.swap_remove(2);
     }
 
+    #[test]
+    fn test_swap() {
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        for value in 0..100 {
+            sut.push(value.to_string());
+        }
+        sut.swap(1, 90);
+        assert_eq!(sut[1], "90");
+        assert_eq!(sut[90], "1");
+        sut.swap(5, 5);
+        assert_eq!(sut[5], "5");
+    }
+
+    #[test]
+    #[should_panic(expected = "swap index (is 10) should be < len (is 10)")]
+    fn test_swap_panic_range() {
+        let mut sut: SegmentArray<u32> = (0..10).collect();
+        sut.swap(3, 10);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_shuffle() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let mut sut: SegmentArray<usize> = (0..10_000).collect();
+        sut.shuffle(&mut rng);
+        assert_eq!(sut.len(), 10_000);
+        assert!(sut.iter().enumerate().any(|(idx, elem)| idx != *elem));
+        let mut values = sut.to_vec_copy();
+        values.sort();
+        assert_eq!(values, (0..10_000).collect::<Vec<usize>>());
+        let mut empty: SegmentArray<usize> = SegmentArray::new();
+        empty.shuffle(&mut rng);
+        assert!(empty.is_empty());
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_choose() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let empty: SegmentArray<usize> = SegmentArray::new();
+        assert!(empty.choose(&mut rng).is_none());
+        let sut: SegmentArray<usize> = (0..1000).collect();
+        for _ in 0..100 {
+            let value = sut.choose(&mut rng).unwrap();
+            assert!(*value < 1000);
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_choose_multiple() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let sut: SegmentArray<usize> = (0..1000).collect();
+        let mut chosen: Vec<usize> = sut
+            .choose_multiple(&mut rng, 100)
+            .into_iter()
+            .copied()
+            .collect();
+        assert_eq!(chosen.len(), 100);
+        chosen.sort();
+        chosen.dedup();
+        assert_eq!(chosen.len(), 100);
+        let chosen = sut.choose_multiple(&mut rng, 5000);
+        assert_eq!(chosen.len(), 1000);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_sample_from_iter() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let sut = SegmentArray::sample_from_iter(0..100_000, &mut rng, 500);
+        assert_eq!(sut.len(), 500);
+        let mut values = sut.to_vec_copy();
+        values.sort();
+        values.dedup();
+        assert_eq!(values.len(), 500);
+        // the sample should not simply be the first elements
+        assert!(values.iter().any(|v| *v >= 500));
+        let sut = SegmentArray::sample_from_iter(0..10, &mut rng, 500);
+        assert_eq!(sut.to_vec_copy(), (0..10).collect::<Vec<i32>>());
+    }
+
     #[test]
     fn test_push_get_thousands_structs() {
         struct MyData {
@@ -912,6 +3092,64 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_iter_resume() {
+        let sut: SegmentArray<usize> = (0..1000).collect();
+        let mut iter = sut.iter();
+        assert_eq!(iter.position_token().index(), 0);
+        for _ in 0..300 {
+            iter.next();
+        }
+        let token = iter.position_token();
+        assert_eq!(token.index(), 300);
+        let resumed: Vec<usize> = sut.iter_resume(token).copied().collect();
+        assert_eq!(resumed, (300..1000).collect::<Vec<usize>>());
+        // token survives conversion to and from a plain offset
+        let restored = IterToken::from_index(token.index());
+        assert_eq!(sut.iter_resume(restored).next(), Some(&300));
+        // exhausted iterator yields a token at the end
+        let mut iter = sut.iter_resume(IterToken::from_index(999));
+        assert_eq!(iter.next(), Some(&999));
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+        assert_eq!(iter.position_token().index(), 1000);
+        assert!(
+            sut.iter_resume(IterToken::from_index(5000))
+                .next()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_send_sync() {
+        let sut: SegmentArray<usize> = (0..10_000).collect();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    assert_eq!(sut.iter().sum::<usize>(), (0..10_000).sum::<usize>());
+                });
+            }
+        });
+        let handle = std::thread::spawn(move || sut.len());
+        assert_eq!(handle.join().unwrap(), 10_000);
+    }
+
+    #[test]
+    fn test_iterator_exact_size() {
+        let sut: SegmentArray<String> = (0..1000).map(|v| v.to_string()).collect();
+        let mut iter = sut.iter();
+        assert_eq!(iter.len(), 1000);
+        iter.nth(99);
+        assert_eq!(iter.size_hint(), (900, Some(900)));
+        assert_eq!(sut.iter().take(10).len(), 10);
+        let mut iter = sut.into_iter();
+        assert_eq!(iter.len(), 1000);
+        iter.nth(99);
+        assert_eq!(iter.len(), 900);
+        let vec: Vec<String> = iter.collect();
+        assert_eq!(vec.capacity(), 900);
+    }
+
     #[test]
     fn test_array_into_iterator() {
         // an array that only requires a single segment
@@ -999,6 +3237,392 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_clone_strings() {
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        for _ in 0..512 {
+            let value = ulid::Ulid::new().to_string();
+            sut.push(value);
+        }
+        let copy = sut.clone();
+        drop(sut);
+        assert_eq!(copy.len(), 512);
+        assert_eq!(copy.capacity(), 960);
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        sut.push(String::from("alone"));
+        let copy = sut.clone();
+        assert_eq!(copy.len(), 1);
+        assert_eq!(copy[0], "alone");
+        let empty: SegmentArray<String> = SegmentArray::new();
+        assert!(empty.clone().is_empty());
+    }
+
+    #[test]
+    fn test_clone_copy_ints() {
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        for value in 0..1000 {
+            sut.push(value);
+        }
+        let copy = sut.clone_copy();
+        assert_eq!(copy.len(), 1000);
+        for (idx, elem) in copy.iter().enumerate() {
+            assert_eq!(idx, *elem as usize);
+        }
+    }
+
+    #[test]
+    fn test_extend_from_slice() {
+        let inputs: Vec<String> = (0..300).map(|v| v.to_string()).collect();
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        sut.push(String::from("first"));
+        sut.extend_from_slice(&inputs);
+        assert_eq!(sut.len(), 301);
+        assert_eq!(sut[0], "first");
+        for (idx, item) in inputs.iter().enumerate() {
+            assert_eq!(&sut[idx + 1], item);
+        }
+        sut.extend_from_slice(&[]);
+        assert_eq!(sut.len(), 301);
+    }
+
+    #[test]
+    fn test_extend_from_slice_copy() {
+        let inputs: Vec<usize> = (0..5000).collect();
+        let mut sut: SegmentArray<usize> = SegmentArray::new();
+        // fill part of the first segment to test a partial segment copy
+        sut.extend_from_slice_copy(&inputs[..10]);
+        sut.extend_from_slice_copy(&inputs[10..]);
+        assert_eq!(sut.len(), 5000);
+        assert_eq!(sut.capacity(), 8128);
+        for (idx, elem) in sut.iter().enumerate() {
+            assert_eq!(idx, *elem);
+        }
+    }
+
+    #[test]
+    fn test_clone_range() {
+        let sut: SegmentArray<String> = (0..1000).map(|v| v.to_string()).collect();
+        let copy = sut.clone_range(60..300);
+        assert_eq!(copy.len(), 240);
+        for (idx, elem) in copy.iter().enumerate() {
+            assert_eq!((idx + 60).to_string(), *elem);
+        }
+        assert_eq!(sut.clone_range(..).len(), 1000);
+        assert_eq!(sut.clone_range(990..).len(), 10);
+        assert_eq!(sut.clone_range(..=10).len(), 11);
+        assert!(sut.clone_range(500..500).is_empty());
+        let copy = sut.clone_range((Bound::Excluded(998), Bound::Unbounded));
+        assert_eq!(copy.len(), 1);
+        assert_eq!(copy[0], "999");
+    }
+
+    #[test]
+    #[should_panic(expected = "range end index 1001 out of range for slice of length 1000")]
+    fn test_clone_range_out_of_bounds() {
+        let sut: SegmentArray<u32> = (0..1000).collect();
+        sut.clone_range(10..1001);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice index starts at 20 but ends at 10")]
+    fn test_clone_range_inverted() {
+        let sut: SegmentArray<u32> = (0..1000).collect();
+        #[allow(clippy::reversed_empty_ranges)]
+        sut.clone_range(20..10);
+    }
+
+    #[test]
+    fn test_try_map() {
+        let sut: SegmentArray<String> = (0..1000).map(|v| v.to_string()).collect();
+        let parsed = sut.try_map(|s| s.parse::<u32>()).unwrap();
+        assert_eq!(parsed.len(), 1000);
+        assert_eq!(parsed.capacity(), sut.capacity());
+        for (idx, value) in parsed.iter().enumerate() {
+            assert_eq!(*value, idx as u32);
+        }
+        let empty: SegmentArray<String> = SegmentArray::new();
+        assert!(empty.try_map(|s| s.parse::<u32>()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_try_map_error() {
+        // converted values are dropped when an error occurs
+        let marker = std::rc::Rc::new(());
+        let sut: SegmentArray<u32> = (0..500).collect();
+        let result = sut.try_map(|v| {
+            if *v == 300 {
+                Err(format!("bad value {v}"))
+            } else {
+                Ok(marker.clone())
+            }
+        });
+        assert_eq!(result.err().unwrap(), "bad value 300");
+        assert_eq!(std::rc::Rc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn test_copy_to_slice() {
+        let sut: SegmentArray<u32> = (0..1000).collect();
+        let mut dest = [0u32; 200];
+        sut.copy_to_slice(50..250, &mut dest);
+        assert!(dest.iter().copied().eq(50..250));
+        let mut dest = vec![0u32; 1000];
+        sut.copy_to_slice(.., &mut dest);
+        assert!(dest.iter().copied().eq(0..1000));
+        sut.copy_to_slice(10..10, &mut []);
+    }
+
+    #[test]
+    fn test_clone_to_slice() {
+        let sut: SegmentArray<String> = (0..1000).map(|v| v.to_string()).collect();
+        let mut dest = vec![String::new(); 100];
+        sut.clone_to_slice(900.., &mut dest);
+        for (idx, elem) in dest.iter().enumerate() {
+            assert_eq!((idx + 900).to_string(), *elem);
+        }
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "source range length (10) does not match destination slice length (9)"
+    )]
+    fn test_copy_to_slice_mismatch() {
+        let sut: SegmentArray<u32> = (0..1000).collect();
+        let mut dest = [0u32; 9];
+        sut.copy_to_slice(0..10, &mut dest);
+    }
+
+    #[test]
+    fn test_to_vec() {
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        for value in 0..200 {
+            sut.push(value.to_string());
+        }
+        let vec = sut.to_vec();
+        assert_eq!(vec.len(), 200);
+        for (idx, elem) in vec.iter().enumerate() {
+            assert_eq!(&sut[idx], elem);
+        }
+        let sut: SegmentArray<i32> = (0..200).collect();
+        let vec = sut.to_vec_copy();
+        assert_eq!(vec, (0..200).collect::<Vec<i32>>());
+        let empty: SegmentArray<i32> = SegmentArray::new();
+        assert!(empty.to_vec_copy().is_empty());
+        // several segments, the last of which is partially filled
+        let sut: SegmentArray<u64> = (0..1000).collect();
+        let vec = sut.to_vec_copy();
+        assert_eq!(vec.capacity(), 1000);
+        assert!(vec.into_iter().eq(0..1000));
+    }
+
+    #[test]
+    fn test_drain_to_vec() {
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        assert!(sut.drain_to_vec().is_empty());
+        for value in 0..1000 {
+            sut.push(value.to_string());
+        }
+        let vec = sut.drain_to_vec();
+        assert_eq!(vec.len(), 1000);
+        assert_eq!(vec.capacity(), 1000);
+        for (idx, elem) in vec.iter().enumerate() {
+            assert_eq!(idx.to_string(), *elem);
+        }
+        assert!(sut.is_empty());
+        assert_eq!(sut.capacity(), 0);
+        sut.push(String::from("reused"));
+        assert_eq!(sut[0], "reused");
+    }
+
+    #[test]
+    fn test_into_boxed_slice() {
+        let sut: SegmentArray<String> = (0..1000).map(|v| v.to_string()).collect();
+        let boxed = sut.into_boxed_slice();
+        assert_eq!(boxed.len(), 1000);
+        for (idx, elem) in boxed.iter().enumerate() {
+            assert_eq!(idx.to_string(), *elem);
+        }
+        let sut: SegmentArray<String> = SegmentArray::new();
+        assert!(sut.into_boxed_slice().is_empty());
+    }
+
+    #[test]
+    fn test_into_flattened() {
+        let mut sut: SegmentArray<[u8; 32]> = SegmentArray::new();
+        for value in 0..100u8 {
+            sut.push([value; 32]);
+        }
+        let flat = sut.into_flattened();
+        assert_eq!(flat.len(), 3200);
+        for (idx, elem) in flat.iter().enumerate() {
+            assert_eq!(*elem as usize, idx / 32);
+        }
+        let mut sut: SegmentArray<[String; 2]> = SegmentArray::new();
+        for value in 0..100 {
+            sut.push([value.to_string(), value.to_string()]);
+        }
+        let flat = sut.into_flattened();
+        assert_eq!(flat.len(), 200);
+        assert_eq!(flat[199], "99");
+        let sut: SegmentArray<[u8; 4]> = SegmentArray::new();
+        assert!(sut.into_flattened().is_empty());
+        // at the peak the budget is charged for the segments of both arrays
+        let budget = MemoryBudget::new(64 * 8 + 192 * 4);
+        let mut sut: SegmentArray<[u32; 2]> = SegmentArray::new();
+        sut.set_memory_budget(budget.clone()).unwrap();
+        sut.extend_from_slice_copy(&[[1, 2]; 64]);
+        assert_eq!(budget.used(), 64 * 8);
+        let flat = sut.into_flattened();
+        assert_eq!(flat.len(), 128);
+        assert_eq!(budget.used(), 192 * 4);
+        drop(flat);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "memory budget exceeded: requested 512 bytes with 511 remaining")]
+    fn test_into_flattened_budget() {
+        let budget = MemoryBudget::new(64 * 8 + 192 * 4 - 1);
+        let mut sut: SegmentArray<[u32; 2]> = SegmentArray::new();
+        sut.set_memory_budget(budget).unwrap();
+        sut.extend_from_slice_copy(&[[1, 2]; 64]);
+        sut.into_flattened();
+    }
+
+    #[test]
+    fn test_flattened_slices() {
+        let mut sut: SegmentArray<[u8; 32]> = SegmentArray::new();
+        for value in 0..100u8 {
+            sut.push([value; 32]);
+        }
+        let lengths: Vec<usize> = sut.flattened_slices().map(|s| s.len()).collect();
+        assert_eq!(lengths, vec![64 * 32, 36 * 32]);
+        let flat: Vec<u8> = sut.flattened_slices().flatten().copied().collect();
+        assert_eq!(flat.len(), 3200);
+        assert_eq!(flat[3199], 99);
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let inputs: Vec<String> = (0..1000).map(|v| v.to_string()).collect();
+        let sut = SegmentArray::from(inputs.clone());
+        assert_eq!(sut.len(), 1000);
+        for (idx, item) in inputs.iter().enumerate() {
+            assert_eq!(&sut[idx], item);
+        }
+        let sut: SegmentArray<String> = SegmentArray::from(Vec::new());
+        assert!(sut.is_empty());
+        assert_eq!(sut.capacity(), 0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_eq() {
+        let a: SegmentArray<usize> = (0..300_000).collect();
+        let mut b: SegmentArray<usize> = (0..300_000).collect();
+        assert!(a.par_eq(&b));
+        b[299_999] = 0;
+        assert!(!a.par_eq(&b));
+        b.pop();
+        assert!(!a.par_eq(&b));
+        let empty: SegmentArray<usize> = SegmentArray::new();
+        assert!(empty.par_eq(&SegmentArray::new()));
+        assert!(!empty.par_eq(&a));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_cmp() {
+        let a: SegmentArray<usize> = (0..300_000).collect();
+        let mut b: SegmentArray<usize> = (0..300_000).collect();
+        assert_eq!(a.par_cmp(&b), Ordering::Equal);
+        b[100] = 0;
+        b[200_000] = 1_000_000;
+        assert_eq!(a.par_cmp(&b), Ordering::Greater);
+        assert_eq!(b.par_cmp(&a), Ordering::Less);
+        assert_eq!(a.par_cmp(&b), a.iter().cmp(b.iter()));
+        let mut c: SegmentArray<usize> = (0..300_000).collect();
+        c.pop();
+        assert_eq!(a.par_cmp(&c), Ordering::Greater);
+        assert_eq!(c.par_cmp(&a), Ordering::Less);
+    }
+
+    #[cfg(feature = "deepsize")]
+    #[test]
+    fn test_deep_size_of() {
+        use deepsize::DeepSizeOf;
+        let mut sut: SegmentArray<String> = SegmentArray::new();
+        let base = size_of::<SegmentArray<String>>();
+        assert_eq!(sut.deep_size_of(), base);
+        for _ in 0..100 {
+            sut.push(String::from("abcdefgh"));
+        }
+        // two segments of 192 slots plus 8 bytes in each string
+        assert_eq!(sut.deep_size_of(), base + 192 * size_of::<String>() + 800);
+    }
+
+    #[cfg(feature = "get-size")]
+    #[test]
+    fn test_get_size() {
+        use get_size::GetSize;
+        let mut sut: SegmentArray<Vec<u16>> = SegmentArray::new();
+        assert_eq!(sut.get_heap_size(), 0);
+        for _ in 0..65 {
+            sut.push(Vec::with_capacity(4));
+        }
+        assert_eq!(sut.get_heap_size(), 192 * size_of::<Vec<u16>>() + 65 * 8);
+        assert_eq!(
+            sut.get_size(),
+            size_of::<SegmentArray<Vec<u16>>>() + sut.get_heap_size()
+        );
+    }
+
+    #[test]
+    fn test_runs() {
+        let sut: SegmentArray<u32> = SegmentArray::new();
+        assert_eq!(sut.runs().next(), None);
+        // runs that span one and several segment boundaries
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        for _ in 0..60 {
+            sut.push(1);
+        }
+        for _ in 0..10 {
+            sut.push(2);
+        }
+        sut.push(3);
+        for _ in 0..500 {
+            sut.push(4);
+        }
+        sut.push(5);
+        let runs: Vec<(usize, u32)> = sut.runs().map(|(n, v)| (n, *v)).collect();
+        assert_eq!(runs, [(60, 1), (10, 2), (1, 3), (500, 4), (1, 5)]);
+        let total: usize = sut.runs().map(|(n, _)| n).sum();
+        assert_eq!(total, sut.len());
+        // run that ends exactly at a segment boundary
+        let sut: SegmentArray<u32> = (0..192).map(|v| v / 64).collect();
+        let runs: Vec<(usize, u32)> = sut.runs().map(|(n, v)| (n, *v)).collect();
+        assert_eq!(runs, [(64, 0), (64, 1), (64, 2)]);
+        // elements that are not equal to themselves form runs of one
+        let sut: SegmentArray<f64> = [1.0, f64::NAN, f64::NAN, 2.0].into_iter().collect();
+        let runs: Vec<(usize, f64)> = sut.runs().map(|(n, v)| (n, *v)).collect();
+        assert_eq!(runs.len(), 4);
+        assert_eq!(runs[0], (1, 1.0));
+        assert!(runs[1..3].iter().all(|(n, v)| *n == 1 && v.is_nan()));
+        assert_eq!(runs[3], (1, 2.0));
+    }
+
+    #[test]
+    fn test_drop_in_background() {
+        let marker = Arc::new(());
+        let mut sut: SegmentArray<Arc<()>> = SegmentArray::new();
+        for _ in 0..1000 {
+            sut.push(marker.clone());
+        }
+        let handle = sut.drop_in_background();
+        handle.join().unwrap();
+        assert_eq!(Arc::strong_count(&marker), 1);
+    }
+
     #[test]
     fn test_push_get_many_instances_ints() {
         // test allocating, filling, and then dropping many instances
@@ -0,0 +1,198 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Layout calculations for the segments of a segment array, for use by other
+//! data structures (such as slabs, arenas, or concurrent variants) that wish
+//! to share the same geometry without copying the constants.
+//!
+//! Element offsets map to segments as follows:
+//!
+//! ```
+//! use segment_array::raw;
+//! assert_eq!(raw::slots_in_segment(0), 64);
+//! assert_eq!(raw::slots_in_segment(1), 128);
+//! assert_eq!(raw::locate(63), (0, 63));
+//! assert_eq!(raw::locate(64), (1, 0));
+//! assert_eq!(raw::capacity_for_segment_count(2), 192);
+//! assert_eq!(raw::segments_for_len(193), 3);
+//! ```
+
+//
+// An individual segment can never be larger than 9,223,372,036,854,775,807
+// bytes due to the mechanics of the Rust memory allocator.
+//
+// 26 segments with 6 skipped segments with each segment doubling in size
+// results in the last segment having 2,147,483,648 items
+//
+// 9,223,372,036,854,775,807 bytes divided by 2,147,483,648 items yields a
+// maximum item size of 4,294,967,295 bytes (see MAX_ELEMENT_SIZE)
+//
+
+/// Number of segments in a segment array.
+pub const MAX_SEGMENT_COUNT: usize = 26;
+
+/// Number of the smallest segment sizes that are not used at all.
+///
+/// Segments of size 1, 2, 4, 8, 16, and 32 are skipped (that is, the smallest
+/// (first) segment is 64 elements in size) to avoid the overhead of such tiny
+/// arrays.
+pub const SMALL_SEGMENTS_TO_SKIP: usize = 6;
+
+/// Number of elements in the first segment.
+pub const SMALL_SEGMENTS_CAPACITY: usize = 1 << SMALL_SEGMENTS_TO_SKIP;
+
+/// Largest element size, in bytes, for which every segment can be allocated.
+///
+/// An allocation can be no larger than `isize::MAX` bytes, which is divided by
+/// the number of elements in the last segment, giving 4,294,967,295 bytes.
+/// Figures such as 2,147,483,680 that are sometimes quoted for this limit do
+/// not follow from the segment sizes and would reject types whose last
+/// segment can in fact be allocated.
+pub const MAX_ELEMENT_SIZE: usize = isize::MAX as usize / slots_in_segment(MAX_SEGMENT_COUNT - 1);
+
+/// Panics if elements of type `T` are too large for the last segment to be
+/// allocated. When evaluated in a constant context, such as by the
+/// constructors of the segment array, this rejects the type at compile time.
+///
+/// The check is only made on 64-bit targets; on smaller targets the address
+/// space is exhausted well before the last segments are reached.
+///
+/// ```compile_fail
+/// # use segment_array::SegmentArray;
+/// let sut: SegmentArray<[u8; 4_294_967_296]> = SegmentArray::new();
+/// ```
+pub const fn check_element_size<T>() {
+    if cfg!(target_pointer_width = "64") {
+        assert!(
+            size_of::<T>() <= MAX_ELEMENT_SIZE,
+            "element type is too large for a segment array"
+        );
+    }
+}
+
+/// Returns the number of elements that will fit into the given segment.
+#[inline]
+pub const fn slots_in_segment(segment: usize) -> usize {
+    SMALL_SEGMENTS_CAPACITY << segment
+}
+
+/// Returns the overall capacity of the given number of segments.
+#[inline]
+pub const fn capacity_for_segment_count(segment: usize) -> usize {
+    (SMALL_SEGMENTS_CAPACITY << segment) - SMALL_SEGMENTS_CAPACITY
+}
+
+/// Returns the segment, and the slot within that segment, for the given
+/// element offset.
+#[inline]
+pub const fn locate(index: usize) -> (usize, usize) {
+    let segment = ((index >> SMALL_SEGMENTS_TO_SKIP) + 1).ilog2() as usize;
+    (segment, index - capacity_for_segment_count(segment))
+}
+
+/// Returns the number of segments needed to hold the given number of
+/// elements.
+#[inline]
+pub const fn segments_for_len(len: usize) -> usize {
+    if len == 0 { 0 } else { locate(len - 1).0 + 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slots_in_segment() {
+        // values are simply capacity_for_segment_count() plus 64 but there
+        // should be a test for this function regardless of its simplicity
+        let expected_values = [
+            64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536, 131072, 262144, 524288,
+            1048576, 2097152, 4194304, 8388608, 16777216, 33554432, 67108864, 134217728, 268435456,
+            536870912, 1073741824, 2147483648,
+        ];
+        assert_eq!(expected_values.len(), MAX_SEGMENT_COUNT);
+        for (segment, item) in expected_values.iter().enumerate() {
+            assert_eq!(*item, slots_in_segment(segment));
+        }
+    }
+
+    #[test]
+    fn test_capacity_for_segment_count() {
+        //
+        // from https://danielchasehooper.com/posts/segment_array/segment_array.h:
+        //
+        // 26 segments with 6 skipped segments can hold 4,294,967,232 items, aka
+        // capacity_for_segment_count(26)
+        //
+        let expected_values = [
+            0, 64, 192, 448, 960, 1984, 4032, 8128, 16320, 32704, 65472, 131008, 262080, 524224,
+            1048512, 2097088, 4194240, 8388544, 16777152, 33554368, 67108800, 134217664, 268435392,
+            536870848, 1073741760, 2147483584, 4294967232,
+        ];
+        assert_eq!(expected_values.len(), MAX_SEGMENT_COUNT + 1);
+        for (count, item) in expected_values.iter().enumerate() {
+            assert_eq!(*item, capacity_for_segment_count(count));
+        }
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_max_element_size() {
+        assert_eq!(MAX_ELEMENT_SIZE, 4_294_967_295);
+        // the last segment of the largest elements can still be described
+        let layout = std::alloc::Layout::array::<[u8; MAX_ELEMENT_SIZE]>(slots_in_segment(
+            MAX_SEGMENT_COUNT - 1,
+        ));
+        assert!(layout.is_ok());
+        // while one more byte per element exceeds the allocation limit
+        let layout = std::alloc::Layout::array::<[u8; MAX_ELEMENT_SIZE + 1]>(slots_in_segment(
+            MAX_SEGMENT_COUNT - 1,
+        ));
+        assert!(layout.is_err());
+        check_element_size::<[u8; MAX_ELEMENT_SIZE]>();
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    #[should_panic(expected = "element type is too large for a segment array")]
+    fn test_check_element_size_too_large() {
+        check_element_size::<[u8; MAX_ELEMENT_SIZE + 1]>();
+    }
+
+    #[test]
+    fn test_locate() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(191), (1, 127));
+        assert_eq!(locate(192), (2, 0));
+        let last = capacity_for_segment_count(MAX_SEGMENT_COUNT) - 1;
+        assert_eq!(
+            locate(last),
+            (
+                MAX_SEGMENT_COUNT - 1,
+                slots_in_segment(MAX_SEGMENT_COUNT - 1) - 1
+            )
+        );
+        for segment in 1..MAX_SEGMENT_COUNT {
+            let start = capacity_for_segment_count(segment);
+            assert_eq!(
+                locate(start - 1),
+                (segment - 1, slots_in_segment(segment - 1) - 1)
+            );
+            assert_eq!(locate(start), (segment, 0));
+        }
+    }
+
+    #[test]
+    fn test_segments_for_len() {
+        assert_eq!(segments_for_len(0), 0);
+        assert_eq!(segments_for_len(1), 1);
+        assert_eq!(segments_for_len(64), 1);
+        assert_eq!(segments_for_len(65), 2);
+        for count in 1..=MAX_SEGMENT_COUNT {
+            let capacity = capacity_for_segment_count(count);
+            assert_eq!(segments_for_len(capacity), count);
+            assert_eq!(segments_for_len(capacity + 1), count + 1);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Table of the segments of an array written entirely in safe code, which
+//! replaces the table of raw pointers when built with
+//! `--cfg segment_array_safe`.
+//!
+//! Each segment is a `Vec` that is allocated with the capacity of that segment
+//! and never grows beyond it, such that the values are never moved once
+//! added. The length of each vector is the number of values in the segment.
+
+use crate::raw::{MAX_SEGMENT_COUNT, capacity_for_segment_count, locate, slots_in_segment};
+use crate::storage::SegmentStorage;
+use std::alloc::Layout;
+use std::io::{self, Read};
+
+///
+/// Types that have a value in which every byte is zero, allowing the use of
+/// [`SegmentArray::with_zeroed`](crate::SegmentArray::with_zeroed).
+///
+pub trait Zeroable: Copy {
+    /// The value with every byte set to zero.
+    const ZERO: Self;
+}
+
+macro_rules! impl_zeroable {
+    ($($t:ty),*) => {
+        $(impl Zeroable for $t {
+            const ZERO: Self = 0;
+        })*
+    };
+}
+
+impl_zeroable!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize
+);
+
+impl Zeroable for f32 {
+    const ZERO: Self = 0.0;
+}
+
+impl Zeroable for f64 {
+    const ZERO: Self = 0.0;
+}
+
+impl Zeroable for bool {
+    const ZERO: Self = false;
+}
+
+impl Zeroable for char {
+    const ZERO: Self = '\0';
+}
+
+impl<T: Zeroable, const N: usize> Zeroable for [T; N] {
+    const ZERO: Self = [T::ZERO; N];
+}
+
+///
+/// Segment that has been allocated in advance, typically on a background
+/// thread, to be added to an array via
+/// [`SegmentArray::install_segment`](crate::SegmentArray::install_segment).
+/// The segment is deallocated if it is dropped without being installed.
+///
+pub struct PreparedSegment<T> {
+    // which segment this allocation is meant for
+    segment: usize,
+    // empty vector with the capacity of the segment, until installed
+    vec: Option<Vec<T>>,
+}
+
+impl<T> PreparedSegment<T> {
+    /// Allocate the given segment, as returned by
+    /// [`SegmentArray::next_segment`](crate::SegmentArray::next_segment).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the segment number exceeds the maximum number of segments, or
+    /// the segment would exceed `isize::MAX` _bytes_.
+    pub fn new(segment: usize) -> Self {
+        assert!(
+            segment < MAX_SEGMENT_COUNT,
+            "maximum number of segments exceeded"
+        );
+        let segment_len = slots_in_segment(segment);
+        let vec = Vec::with_capacity(segment_len);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            segment,
+            bytes = segment_len * size_of::<T>(),
+            "segment prepared"
+        );
+        Self {
+            segment,
+            vec: Some(vec),
+        }
+    }
+
+    /// Returns the number of the segment that was allocated.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+}
+
+impl<T> Drop for PreparedSegment<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracing")]
+        if self.vec.is_some() {
+            tracing::debug!(
+                segment = self.segment,
+                bytes = slots_in_segment(self.segment) * size_of::<T>(),
+                "prepared segment deallocated"
+            );
+        }
+    }
+}
+
+///
+/// Vectors that hold the values of the allocated segments of an array.
+///
+pub(crate) struct Segments<T> {
+    // number of allocated segments
+    used: usize,
+    // allocated segments (0 to used-1), the others have no capacity
+    vecs: [Vec<T>; MAX_SEGMENT_COUNT],
+}
+
+impl<T> Segments<T> {
+    /// Return a table with no allocated segments.
+    pub(crate) const fn new() -> Self {
+        Self {
+            used: 0,
+            vecs: [const { Vec::new() }; MAX_SEGMENT_COUNT],
+        }
+    }
+
+    /// Returns the number of allocated segments.
+    #[inline]
+    pub(crate) fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Allocate the next segment, after the storage has agreed to the
+    /// allocation. Returns false if either the storage or the allocator
+    /// refused. The values of a zeroed segment are added by `init_zeroed()`.
+    pub(crate) fn allocate<S: SegmentStorage>(
+        &mut self,
+        storage: &S,
+        layout: Layout,
+        zeroed: bool,
+    ) -> bool {
+        let _ = zeroed;
+        if !storage.allocate(layout) {
+            return false;
+        }
+        let mut vec = Vec::new();
+        if vec.try_reserve_exact(slots_in_segment(self.used)).is_err() {
+            storage.deallocate(layout);
+            return false;
+        }
+        self.vecs[self.used] = vec;
+        self.used += 1;
+        true
+    }
+
+    /// Release the last segment, which must hold no values, and inform the
+    /// storage.
+    pub(crate) fn deallocate<S: SegmentStorage>(&mut self, storage: &S, layout: Layout) {
+        self.used -= 1;
+        self.vecs[self.used] = Vec::new();
+        storage.deallocate(layout);
+    }
+
+    /// Adds a segment that was allocated in advance, which must be the next
+    /// segment of the table.
+    pub(crate) fn install(&mut self, mut prepared: PreparedSegment<T>) {
+        debug_assert_eq!(prepared.segment, self.used);
+        self.vecs[self.used] = prepared.vec.take().expect("segment not yet installed");
+        self.used += 1;
+    }
+
+    /// Adds zeros to the segments until there are `len` values.
+    pub(crate) fn init_zeroed(&mut self, len: usize)
+    where
+        T: Zeroable,
+    {
+        for (segment, vec) in self.vecs[..self.used].iter_mut().enumerate() {
+            let start = capacity_for_segment_count(segment);
+            let segment_len = slots_in_segment(segment).min(len.saturating_sub(start));
+            vec.resize(segment_len, T::ZERO);
+        }
+    }
+
+    /// Adds the value to the segment for the given offset, which must be the
+    /// first slot that does not hold a value.
+    #[inline]
+    pub(crate) fn write(&mut self, index: usize, value: T) {
+        let (segment, slot) = locate(index);
+        debug_assert_eq!(self.vecs[segment].len(), slot);
+        self.vecs[segment].push(value);
+    }
+
+    /// Removes the value at the given offset, which must be the last that
+    /// holds a value.
+    #[inline]
+    pub(crate) fn read(&mut self, index: usize) -> T {
+        let (segment, slot) = locate(index);
+        debug_assert_eq!(self.vecs[segment].len(), slot + 1);
+        self.vecs[segment].pop().expect("slot holds a value")
+    }
+
+    /// Removes the value at the given offset for an iterator that consumes
+    /// the values from the first to the last.
+    ///
+    /// Upon taking the first value of a segment, its values are reversed such
+    /// that each can then be popped from the end of the vector.
+    #[inline]
+    pub(crate) fn take(&mut self, index: usize) -> T {
+        let (segment, slot) = locate(index);
+        let vec = &mut self.vecs[segment];
+        if slot == 0 {
+            vec.reverse();
+        }
+        vec.pop().expect("slot holds a value")
+    }
+
+    /// Returns a reference to the value at the given offset.
+    #[inline]
+    pub(crate) fn get(&self, index: usize) -> &T {
+        let (segment, slot) = locate(index);
+        &self.vecs[segment][slot]
+    }
+
+    /// Does nothing, as a prefetch hint cannot be given in safe code.
+    #[inline]
+    pub(crate) fn prefetch(&self, index: usize) {
+        let _ = index;
+    }
+
+    /// Returns a mutable reference to the value at the given offset.
+    #[inline]
+    pub(crate) fn get_mut(&mut self, index: usize) -> &mut T {
+        let (segment, slot) = locate(index);
+        &mut self.vecs[segment][slot]
+    }
+
+    /// Swaps the values at the two offsets.
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        let (segment_a, slot_a) = locate(a);
+        let (segment_b, slot_b) = locate(b);
+        if segment_a == segment_b {
+            self.vecs[segment_a].swap(slot_a, slot_b);
+        } else {
+            let (low, high) = self.vecs.split_at_mut(segment_a.max(segment_b));
+            let (low_slot, high_slot) = if segment_a < segment_b {
+                (slot_a, slot_b)
+            } else {
+                (slot_b, slot_a)
+            };
+            std::mem::swap(
+                &mut low[segment_a.min(segment_b)][low_slot],
+                &mut high[0][high_slot],
+            );
+        }
+    }
+
+    /// Removes the value at `index`, replacing it with the value at `last`,
+    /// which must be the last that holds a value.
+    pub(crate) fn swap_remove(&mut self, index: usize, last: usize) -> T {
+        let value = self.read(last);
+        if index == last {
+            value
+        } else {
+            std::mem::replace(self.get_mut(index), value)
+        }
+    }
+
+    /// Returns the portions of the segments that make up the given range of
+    /// slots, all of which must hold values, in order.
+    pub(crate) fn slices(&self, start: usize, end: usize) -> impl Iterator<Item = &[T]> {
+        let mut index = start;
+        std::iter::from_fn(move || {
+            if index >= end {
+                return None;
+            }
+            let (segment, slot) = locate(index);
+            let len = (slots_in_segment(segment) - slot).min(end - index);
+            let slice = &self.vecs[segment][slot..slot + len];
+            index += len;
+            Some(slice)
+        })
+    }
+
+    /// Adds `len` values from the iterator to the segments starting at the
+    /// given offset, which must be allocated and not hold values.
+    fn extend_iter<I: Iterator<Item = T>>(&mut self, index: usize, len: usize, values: &mut I) {
+        let mut added = 0;
+        while added < len {
+            let (segment, slot) = locate(index + added);
+            let chunk = (slots_in_segment(segment) - slot).min(len - added);
+            self.vecs[segment].extend(values.by_ref().take(chunk));
+            added += chunk;
+        }
+    }
+
+    /// Copies the values into the slots starting at the given offset, which
+    /// must be allocated and not hold values.
+    pub(crate) fn extend_copy(&mut self, index: usize, values: &[T])
+    where
+        T: Copy,
+    {
+        let mut copied = 0;
+        while copied < values.len() {
+            let (segment, slot) = locate(index + copied);
+            let chunk = (slots_in_segment(segment) - slot).min(values.len() - copied);
+            self.vecs[segment].extend_from_slice(&values[copied..copied + chunk]);
+            copied += chunk;
+        }
+    }
+
+    /// Moves the values of the vector into the slots starting at the given
+    /// offset, which must be allocated and not hold values.
+    pub(crate) fn extend_vec(&mut self, index: usize, values: Vec<T>) {
+        let len = values.len();
+        self.extend_iter(index, len, &mut values.into_iter());
+    }
+
+    /// Moves the first `len` values of another table, flattening them, into
+    /// the slots starting at the given offset, which must be allocated and
+    /// not hold values. The slots of the other table no longer hold values.
+    pub(crate) fn extend_flattened<const N: usize>(
+        &mut self,
+        index: usize,
+        other: &mut Segments<[T; N]>,
+        len: usize,
+    ) {
+        let mut values = other.vecs[..other.used]
+            .iter_mut()
+            .flat_map(|vec| vec.drain(..))
+            .flatten();
+        self.extend_iter(index, len * N, &mut values);
+    }
+
+    /// Copies the first `len` values into a new vector of the exact size.
+    pub(crate) fn copy_to_vec(&self, len: usize) -> Vec<T>
+    where
+        T: Copy,
+    {
+        let mut vec: Vec<T> = Vec::with_capacity(len);
+        for slice in self.slices(0, len) {
+            vec.extend_from_slice(slice);
+        }
+        vec
+    }
+
+    /// Moves the first `len` values into a new vector of the exact size,
+    /// after which the slots no longer hold values.
+    pub(crate) fn drain_to_vec(&mut self, len: usize) -> Vec<T> {
+        let mut vec: Vec<T> = Vec::with_capacity(len);
+        for segment in self.vecs[..self.used].iter_mut() {
+            vec.append(segment);
+        }
+        vec
+    }
+
+    /// Drops the values in the given range of slots, which must lie within a
+    /// single segment and extend to the last value in that segment. The slots
+    /// are considered to no longer hold values, even if a destructor panics.
+    pub(crate) fn drop_slice(&mut self, start: usize, end: usize) {
+        let (segment, _) = locate(start);
+        let vec = &mut self.vecs[segment];
+        // after take() the remaining values are reversed, but are still the
+        // last values of the vector
+        vec.truncate(vec.len() - (end - start));
+    }
+}
+
+impl Segments<u8> {
+    /// Offers the `len` slots starting at the given offset, which must be the
+    /// first that does not hold a value, to the reader. Returns the number of
+    /// bytes read, which now hold values.
+    ///
+    /// The slots are zeroed before every read, `zeroed` is only used by the
+    /// raw pointer implementation.
+    pub(crate) fn read_spare<R: Read + ?Sized>(
+        &mut self,
+        index: usize,
+        len: usize,
+        zeroed: &mut usize,
+        reader: &mut R,
+    ) -> io::Result<usize> {
+        let _ = zeroed;
+        let (segment, slot) = locate(index);
+        let vec = &mut self.vecs[segment];
+        debug_assert_eq!(vec.len(), slot);
+        vec.resize(slot + len, 0);
+        let result = reader.read(&mut vec[slot..]).map(|read| read.min(len));
+        vec.truncate(slot + *result.as_ref().unwrap_or(&0));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SegmentArray;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_partially_consumed_into_iter() {
+        let marker = Arc::new(());
+        let sut: SegmentArray<(usize, Arc<()>)> =
+            (0..300).map(|value| (value, marker.clone())).collect();
+        let mut iter = sut.into_iter();
+        // stop partway through a segment whose values have been reversed
+        for expected in 0..100 {
+            assert_eq!(iter.next().map(|(value, _)| value), Some(expected));
+        }
+        assert_eq!(iter.len(), 200);
+        assert_eq!(Arc::strong_count(&marker), 201);
+        drop(iter);
+        assert_eq!(Arc::strong_count(&marker), 1);
+    }
+}
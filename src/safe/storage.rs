@@ -0,0 +1,163 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Backends that account for the memory of the segments of an array, which
+//! replace the storage backends when built with `--cfg segment_array_safe`.
+//!
+//! Without `unsafe` code the memory of every segment is a `Vec` allocated
+//! from the global allocator, hence the storage cannot provide the memory,
+//! only decide whether each segment may be allocated. The fixed-size buffer,
+//! memory mappings, and segment pool are not available with this feature.
+
+use std::alloc::{Layout, handle_alloc_error};
+#[cfg(feature = "test-support")]
+use std::sync::Arc;
+#[cfg(feature = "test-support")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+///
+/// Gatekeeper for the allocation of the segments of an array.
+///
+/// The storage is cloned when a new array is derived from an existing one,
+/// such as with `clone()`, and hence should be a cheap handle.
+///
+/// Each array deallocates its segments in the reverse order in which they
+/// were allocated, which implementations are free to rely upon.
+///
+pub trait SegmentStorage: Clone {
+    /// Invoked before a segment of the given layout is allocated, returning
+    /// false to fail the allocation.
+    fn allocate(&self, layout: Layout) -> bool {
+        let _ = layout;
+        true
+    }
+
+    /// Invoked after a segment that was allowed by `allocate()` has been
+    /// released, or could not be allocated after all.
+    fn deallocate(&self, layout: Layout) {
+        let _ = layout;
+    }
+
+    /// Invoked when an allocation fails in an operation that cannot return an
+    /// error, such as `push()`. The default implementation calls
+    /// `std::alloc::handle_alloc_error()`, which typically aborts.
+    fn allocation_failed(&self, layout: Layout) -> ! {
+        handle_alloc_error(layout)
+    }
+}
+
+///
+/// Storage that allocates segments from the global allocator.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Heap;
+
+impl SegmentStorage for Heap {}
+
+///
+/// Storage that fails a chosen allocation (counting from 1), for testing the
+/// handling of allocation failures. Clones of the storage, such as those used
+/// by cloned arrays, share the same count of allocations.
+///
+/// ```
+/// # use segment_array::{FailingStorage, SegmentArray};
+/// let mut arr = SegmentArray::new_in(FailingStorage::new(2));
+/// arr.extend_from_slice_copy(&[1u8; 64]);
+/// assert!(arr.try_reserve(65).is_err());
+/// ```
+///
+#[cfg(feature = "test-support")]
+#[derive(Clone, Debug)]
+pub struct FailingStorage<S: SegmentStorage = Heap> {
+    inner: S,
+    fail_at: usize,
+    // number of allocations attempted, shared by all clones
+    attempts: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "test-support")]
+impl FailingStorage {
+    /// Return a storage that allocates from the heap, failing the allocation
+    /// with the given number.
+    pub fn new(fail_at: usize) -> Self {
+        Self::wrap(Heap, fail_at)
+    }
+}
+
+#[cfg(feature = "test-support")]
+impl<S: SegmentStorage> FailingStorage<S> {
+    /// Return a storage that allocates from the given storage, failing the
+    /// allocation with the given number.
+    pub fn wrap(inner: S, fail_at: usize) -> Self {
+        Self {
+            inner,
+            fail_at,
+            attempts: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the number of allocations that have been attempted.
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    fn should_fail(&self) -> bool {
+        self.attempts.fetch_add(1, Ordering::Relaxed) + 1 == self.fail_at
+    }
+}
+
+#[cfg(feature = "test-support")]
+impl<S: SegmentStorage> SegmentStorage for FailingStorage<S> {
+    fn allocate(&self, layout: Layout) -> bool {
+        !self.should_fail() && self.inner.allocate(layout)
+    }
+
+    fn deallocate(&self, layout: Layout) {
+        self.inner.deallocate(layout)
+    }
+
+    fn allocation_failed(&self, layout: Layout) -> ! {
+        panic!("memory allocation of {} bytes failed", layout.size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentArray;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Storage that tracks the number of bytes currently allocated.
+    #[derive(Clone, Default)]
+    struct Counting(Arc<AtomicUsize>);
+
+    impl SegmentStorage for Counting {
+        fn allocate(&self, layout: Layout) -> bool {
+            self.0.fetch_add(layout.size(), Ordering::Relaxed);
+            true
+        }
+
+        fn deallocate(&self, layout: Layout) {
+            self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_custom_storage() {
+        let storage = Counting::default();
+        let mut sut: SegmentArray<u64, Counting> = SegmentArray::new_in(storage.clone());
+        sut.extend_from_slice_copy(&[1; 100]);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 192 * 8);
+        // clones allocate from the same storage
+        let copy = sut.clone();
+        assert_eq!(storage.0.load(Ordering::Relaxed), 2 * 192 * 8);
+        drop(sut);
+        let zeroed: SegmentArray<u64, _> = SegmentArray::with_zeroed_in(10, storage.clone());
+        assert!(zeroed.iter().all(|v| *v == 0));
+        drop(zeroed);
+        assert_eq!(copy.into_iter().sum::<u64>(), 100);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,148 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Annotations that inform memory checking tools which slots of a segment hold
+//! values, such that reading past the length of the array is reported as an
+//! error even though the memory has been allocated.
+//!
+//! With the `asan` feature, the unused slots are poisoned using the manual
+//! poisoning interface of AddressSanitizer, which requires that the program
+//! is built with `-Zsanitizer=address` (otherwise linking will fail). With the
+//! `valgrind` feature, client requests are issued to the Memcheck tool, which
+//! are ignored when the program is not running under Valgrind.
+
+#[cfg(feature = "asan")]
+unsafe extern "C" {
+    fn __asan_poison_memory_region(addr: *const u8, size: usize);
+    fn __asan_unpoison_memory_region(addr: *const u8, size: usize);
+}
+
+/// Mark the bytes as no longer holding a value, such that any access is
+/// reported as an error.
+#[inline]
+pub(crate) unsafe fn mark_unused(ptr: *const u8, len: usize) {
+    #[cfg(feature = "asan")]
+    unsafe {
+        __asan_poison_memory_region(ptr, len)
+    };
+    #[cfg(feature = "valgrind")]
+    unsafe {
+        valgrind::make_mem_noaccess(ptr, len)
+    };
+    let _ = (ptr, len);
+}
+
+/// Mark the bytes as about to receive a value, permitting access.
+#[inline]
+pub(crate) unsafe fn mark_used(ptr: *const u8, len: usize) {
+    #[cfg(feature = "asan")]
+    unsafe {
+        __asan_unpoison_memory_region(ptr, len)
+    };
+    #[cfg(feature = "valgrind")]
+    unsafe {
+        valgrind::make_mem_undefined(ptr, len)
+    };
+    let _ = (ptr, len);
+}
+
+#[cfg(feature = "valgrind")]
+mod valgrind {
+    // Memcheck client request codes, as defined in memcheck.h
+    const MAKE_MEM_NOACCESS: usize = 0x4d43_0000;
+    const MAKE_MEM_UNDEFINED: usize = 0x4d43_0001;
+
+    pub(super) unsafe fn make_mem_noaccess(ptr: *const u8, len: usize) {
+        unsafe { client_request(MAKE_MEM_NOACCESS, ptr as usize, len) };
+    }
+
+    pub(super) unsafe fn make_mem_undefined(ptr: *const u8, len: usize) {
+        unsafe { client_request(MAKE_MEM_UNDEFINED, ptr as usize, len) };
+    }
+
+    // Issue a client request using the "magic" instruction sequence from
+    // valgrind.h, which has no effect when running natively.
+    #[cfg(target_arch = "x86_64")]
+    unsafe fn client_request(request: usize, addr: usize, len: usize) {
+        let args: [usize; 6] = [request, addr, len, 0, 0, 0];
+        unsafe {
+            std::arch::asm!(
+                "rol rdi, 3",
+                "rol rdi, 13",
+                "rol rdi, 61",
+                "rol rdi, 51",
+                "xchg rbx, rbx",
+                in("rax") args.as_ptr(),
+                inout("rdx") 0usize => _,
+                options(nostack),
+            );
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe fn client_request(request: usize, addr: usize, len: usize) {
+        let args: [usize; 6] = [request, addr, len, 0, 0, 0];
+        unsafe {
+            std::arch::asm!(
+                "ror x12, x12, #3",
+                "ror x12, x12, #13",
+                "ror x12, x12, #51",
+                "ror x12, x12, #61",
+                "orr x10, x10, x10",
+                in("x4") args.as_ptr(),
+                inout("x3") 0usize => _,
+                options(nostack),
+            );
+        }
+    }
+
+    // client requests are not supported on other architectures
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    unsafe fn client_request(_request: usize, _addr: usize, _len: usize) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "asan")]
+    unsafe extern "C" {
+        fn __asan_address_is_poisoned(addr: *const u8) -> i32;
+    }
+
+    #[cfg(feature = "asan")]
+    #[test]
+    fn test_poison_unused_capacity() {
+        use crate::SegmentArray;
+        let is_poisoned = |ptr: *const u32| unsafe { __asan_address_is_poisoned(ptr.cast()) != 0 };
+        let mut sut: SegmentArray<u32> = SegmentArray::new();
+        for value in 0..100 {
+            sut.push(value);
+        }
+        assert!(!is_poisoned(sut.segments.raw_slot(99)));
+        assert!(is_poisoned(sut.segments.raw_slot(100)));
+        assert!(is_poisoned(sut.segments.raw_slot(191)));
+        sut.pop();
+        assert!(is_poisoned(sut.segments.raw_slot(99)));
+        sut.push(7);
+        assert!(!is_poisoned(sut.segments.raw_slot(99)));
+        sut.extend_from_slice_copy(&[1, 2, 3]);
+        assert!(!is_poisoned(sut.segments.raw_slot(102)));
+        assert!(is_poisoned(sut.segments.raw_slot(103)));
+    }
+
+    #[test]
+    fn test_mark_used_unused() {
+        // the annotations must not disturb the contents when the program is
+        // not running under a memory checking tool
+        let mut buffer = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        unsafe {
+            mark_used(buffer.as_ptr(), buffer.len());
+            buffer[0] = 9;
+            mark_unused(buffer.as_ptr().add(4), 4);
+            mark_used(buffer.as_ptr().add(4), 4);
+        }
+        assert_eq!(buffer, [9, 2, 3, 4, 5, 6, 7, 8]);
+    }
+}
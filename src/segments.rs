@@ -0,0 +1,464 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Table of pointers to the segments of an array, which is where the raw
+//! memory of the segments is read and written. The array keeps track of the
+//! length and ensures that the slots given to these functions are in the
+//! expected state (holding a value or not).
+//!
+//! When built with `--cfg segment_array_safe`, this module is replaced by one
+//! of the same shape that is written entirely in safe code.
+
+use super::raw::{MAX_SEGMENT_COUNT, capacity_for_segment_count, locate, slots_in_segment};
+use super::sanitize;
+use super::storage::SegmentStorage;
+use std::alloc::{Layout, alloc, dealloc, handle_alloc_error};
+use std::io::{self, Read};
+
+// Byte patterns written to slots that do not hold a value when the `poison`
+// feature is enabled, following the convention of the MSVC debug heap: slots
+// that have never held a value, and slots whose value has been removed.
+const UNINIT_POISON: u8 = 0xCD;
+const REMOVED_POISON: u8 = 0xDD;
+
+// Fills the given slots with the byte pattern if the `poison` feature is
+// enabled, and marks them as unused for memory checking tools.
+#[inline]
+unsafe fn poison_slots<T>(ptr: *mut T, len: usize, pattern: u8) {
+    #[cfg(feature = "poison")]
+    unsafe {
+        ptr.cast::<u8>().write_bytes(pattern, len * size_of::<T>());
+    }
+    #[cfg(not(feature = "poison"))]
+    let _ = pattern;
+    unsafe { sanitize::mark_unused(ptr.cast(), len * size_of::<T>()) };
+}
+
+// Marks the given slots as about to receive values, undoing the effect of
+// `poison_slots()` for memory checking tools.
+#[inline]
+unsafe fn unpoison_slots<T>(ptr: *mut T, len: usize) {
+    unsafe { sanitize::mark_used(ptr.cast(), len * size_of::<T>()) };
+}
+
+///
+/// Types for which a value with every byte set to zero is valid, allowing the
+/// use of [`SegmentArray::with_zeroed`](crate::SegmentArray::with_zeroed).
+///
+/// # Safety
+///
+/// Implementing this trait for a type that is not valid when all of its bytes
+/// are zero, such as references or `NonZero` integers, is undefined behavior.
+///
+pub unsafe trait Zeroable {}
+
+macro_rules! impl_zeroable {
+    ($($t:ty),*) => {
+        $(unsafe impl Zeroable for $t {})*
+    };
+}
+
+impl_zeroable!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char
+);
+
+unsafe impl<T: Zeroable, const N: usize> Zeroable for [T; N] {}
+
+///
+/// Segment that has been allocated in advance, typically on a background
+/// thread, to be added to an array via
+/// [`SegmentArray::install_segment`](crate::SegmentArray::install_segment).
+/// The segment is deallocated if it is dropped without being installed.
+///
+pub struct PreparedSegment<T> {
+    // which segment this allocation is meant for
+    segment: usize,
+    // pointer to the uninitialized segment
+    ptr: *mut T,
+}
+
+// the segment holds no values of type T, only uninitialized memory
+unsafe impl<T> Send for PreparedSegment<T> {}
+
+impl<T> PreparedSegment<T> {
+    /// Allocate the given segment, as returned by
+    /// [`SegmentArray::next_segment`](crate::SegmentArray::next_segment).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the segment number exceeds the maximum number of segments, or
+    /// the segment would exceed `isize::MAX` _bytes_.
+    pub fn new(segment: usize) -> Self {
+        assert!(
+            segment < MAX_SEGMENT_COUNT,
+            "maximum number of segments exceeded"
+        );
+        let segment_len = slots_in_segment(segment);
+        let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
+        let ptr = unsafe { alloc(layout).cast::<T>() };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(segment, bytes = layout.size(), "segment prepared");
+        Self { segment, ptr }
+    }
+
+    /// Returns the number of the segment that was allocated.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+}
+
+impl<T> Drop for PreparedSegment<T> {
+    fn drop(&mut self) {
+        let segment_len = slots_in_segment(self.segment);
+        let layout = Layout::array::<T>(segment_len).expect("unexpected overflow");
+        unsafe {
+            dealloc(self.ptr as *mut u8, layout);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            segment = self.segment,
+            bytes = layout.size(),
+            "prepared segment deallocated"
+        );
+    }
+}
+
+///
+/// Pointers to the allocated segments of an array.
+///
+pub(crate) struct Segments<T> {
+    // number of allocated segments
+    used: usize,
+    // pointers to allocated segments (0 to used-1)
+    ptrs: [*mut T; MAX_SEGMENT_COUNT],
+}
+
+// the array owns its values much like Vec
+unsafe impl<T: Send> Send for Segments<T> {}
+unsafe impl<T: Sync> Sync for Segments<T> {}
+
+impl<T> Segments<T> {
+    /// Return a table with no allocated segments.
+    pub(crate) const fn new() -> Self {
+        Self {
+            used: 0,
+            ptrs: [std::ptr::null_mut::<T>(); MAX_SEGMENT_COUNT],
+        }
+    }
+
+    /// Returns the number of allocated segments.
+    #[inline]
+    pub(crate) fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Returns a pointer to the slot for the given offset, which must be less
+    /// than the capacity of the array.
+    #[inline]
+    fn slot_ptr(&self, index: usize) -> *mut T {
+        let (segment, slot) = locate(index);
+        unsafe { self.ptrs[segment].add(slot) }
+    }
+
+    /// Returns a pointer to the slot for the given offset, for inspecting
+    /// the poisoned slots in tests.
+    #[cfg(all(test, any(feature = "poison", feature = "asan")))]
+    pub(crate) fn raw_slot(&self, index: usize) -> *mut T {
+        self.slot_ptr(index)
+    }
+
+    /// Allocate the next segment from the storage, with the memory filled
+    /// with zeros if `zeroed` is true. Returns false if the storage failed to
+    /// allocate the memory.
+    pub(crate) fn allocate<S: SegmentStorage>(
+        &mut self,
+        storage: &S,
+        layout: Layout,
+        zeroed: bool,
+    ) -> bool {
+        let ptr = if zeroed {
+            storage.allocate_zeroed(layout).cast::<T>()
+        } else {
+            storage.allocate(layout).cast::<T>()
+        };
+        if ptr.is_null() {
+            return false;
+        }
+        if !zeroed {
+            unsafe { poison_slots(ptr, slots_in_segment(self.used), UNINIT_POISON) };
+        }
+        self.ptrs[self.used] = ptr;
+        self.used += 1;
+        true
+    }
+
+    /// Return the memory of the last segment, which must hold no values, to
+    /// the storage.
+    pub(crate) fn deallocate<S: SegmentStorage>(&mut self, storage: &S, layout: Layout) {
+        self.used -= 1;
+        let ptr = std::mem::replace(&mut self.ptrs[self.used], std::ptr::null_mut());
+        unsafe {
+            unpoison_slots(ptr, slots_in_segment(self.used));
+            storage.deallocate(ptr as *mut u8, layout);
+        }
+    }
+
+    /// Adds a segment that was allocated in advance, which must be the next
+    /// segment of the table.
+    pub(crate) fn install(&mut self, prepared: PreparedSegment<T>) {
+        debug_assert_eq!(prepared.segment, self.used);
+        let prepared = std::mem::ManuallyDrop::new(prepared);
+        unsafe { poison_slots(prepared.ptr, slots_in_segment(self.used), UNINIT_POISON) };
+        self.ptrs[self.used] = prepared.ptr;
+        self.used += 1;
+    }
+
+    /// Takes the zero-filled slots preceding `len` as holding values, in
+    /// segments that were allocated with `zeroed` set to true.
+    pub(crate) fn init_zeroed(&mut self, len: usize)
+    where
+        T: Zeroable,
+    {
+        let capacity = capacity_for_segment_count(self.used);
+        if len < capacity {
+            unsafe { poison_slots(self.slot_ptr(len), capacity - len, UNINIT_POISON) };
+        }
+    }
+
+    /// Moves the value into the slot at the given offset, which must be the
+    /// first slot that does not hold a value.
+    #[inline]
+    pub(crate) fn write(&mut self, index: usize, value: T) {
+        unsafe {
+            let ptr = self.slot_ptr(index);
+            unpoison_slots(ptr, 1);
+            ptr.write(value);
+        }
+    }
+
+    /// Moves the value out of the slot at the given offset, which must be the
+    /// last slot that holds a value.
+    #[inline]
+    pub(crate) fn read(&mut self, index: usize) -> T {
+        unsafe {
+            let ptr = self.slot_ptr(index);
+            let value = ptr.read();
+            poison_slots(ptr, 1, REMOVED_POISON);
+            value
+        }
+    }
+
+    /// Moves the value out of the slot at the given offset for an iterator
+    /// that consumes the values from the first to the last.
+    #[inline]
+    pub(crate) fn take(&mut self, index: usize) -> T {
+        self.read(index)
+    }
+
+    /// Returns a reference to the value at the given offset.
+    #[inline]
+    pub(crate) fn get(&self, index: usize) -> &T {
+        unsafe { &*self.slot_ptr(index) }
+    }
+
+    /// Hints to the processor that the value at the given offset, which must
+    /// hold a value, will soon be read.
+    #[inline]
+    pub(crate) fn prefetch(&self, index: usize) {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            use std::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+            _mm_prefetch::<_MM_HINT_T0>(self.slot_ptr(index).cast());
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = index;
+    }
+
+    /// Returns a mutable reference to the value at the given offset.
+    #[inline]
+    pub(crate) fn get_mut(&mut self, index: usize) -> &mut T {
+        unsafe { &mut *self.slot_ptr(index) }
+    }
+
+    /// Swaps the values at the two offsets.
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        unsafe { std::ptr::swap(self.slot_ptr(a), self.slot_ptr(b)) };
+    }
+
+    /// Moves the value out of the slot at `index`, replacing it with the
+    /// value in the slot at `last`, which must be the last that holds a value.
+    pub(crate) fn swap_remove(&mut self, index: usize, last: usize) -> T {
+        unsafe {
+            let index_ptr = self.slot_ptr(index);
+            let value = index_ptr.read();
+            let last_ptr = self.slot_ptr(last);
+            std::ptr::copy(last_ptr, index_ptr, 1);
+            poison_slots(last_ptr, 1, REMOVED_POISON);
+            value
+        }
+    }
+
+    /// Returns the portions of the segments that make up the given range of
+    /// slots, all of which must hold values, in order.
+    pub(crate) fn slices(&self, start: usize, end: usize) -> impl Iterator<Item = &[T]> {
+        let mut index = start;
+        std::iter::from_fn(move || {
+            if index >= end {
+                return None;
+            }
+            let (segment, slot) = locate(index);
+            let len = (slots_in_segment(segment) - slot).min(end - index);
+            let slice = unsafe { std::slice::from_raw_parts(self.ptrs[segment].add(slot), len) };
+            index += len;
+            Some(slice)
+        })
+    }
+
+    /// Bitwise copy `len` values from `src` into the slots starting at the
+    /// given offset, which must be allocated and not hold values, one segment
+    /// at a time.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `src` is valid for `len` reads and that the
+    /// copied values are not used (or dropped) again by the caller unless `T`
+    /// is `Copy`.
+    unsafe fn append_raw(&mut self, index: usize, src: *const T, len: usize) {
+        let mut copied = 0;
+        while copied < len {
+            let (segment, slot) = locate(index + copied);
+            let chunk = (slots_in_segment(segment) - slot).min(len - copied);
+            unsafe {
+                let dst = self.ptrs[segment].add(slot);
+                unpoison_slots(dst, chunk);
+                std::ptr::copy_nonoverlapping(src.add(copied), dst, chunk);
+            }
+            copied += chunk;
+        }
+    }
+
+    /// Copies the values into the slots starting at the given offset, which
+    /// must be allocated and not hold values.
+    pub(crate) fn extend_copy(&mut self, index: usize, values: &[T])
+    where
+        T: Copy,
+    {
+        unsafe { self.append_raw(index, values.as_ptr(), values.len()) }
+    }
+
+    /// Moves the values of the vector into the slots starting at the given
+    /// offset, which must be allocated and not hold values.
+    pub(crate) fn extend_vec(&mut self, index: usize, mut values: Vec<T>) {
+        let len = values.len();
+        unsafe {
+            // the vector no longer owns the values, only the buffer
+            values.set_len(0);
+            self.append_raw(index, values.as_ptr(), len);
+        }
+    }
+
+    /// Moves the first `len` values of another table, flattening them, into
+    /// the slots starting at the given offset, which must be allocated and
+    /// not hold values. The slots of the other table no longer hold values.
+    pub(crate) fn extend_flattened<const N: usize>(
+        &mut self,
+        index: usize,
+        other: &mut Segments<[T; N]>,
+        len: usize,
+    ) {
+        let mut copied = 0;
+        for slice in other.slices(0, len) {
+            let flat = slice.as_flattened();
+            unsafe { self.append_raw(index + copied, flat.as_ptr(), flat.len()) };
+            copied += flat.len();
+        }
+    }
+
+    /// Copies the first `len` values into a new vector of the exact size.
+    pub(crate) fn copy_to_vec(&self, len: usize) -> Vec<T>
+    where
+        T: Copy,
+    {
+        unsafe { self.copy_out(len) }
+    }
+
+    /// Moves the first `len` values into a new vector of the exact size,
+    /// after which the slots no longer hold values.
+    pub(crate) fn drain_to_vec(&mut self, len: usize) -> Vec<T> {
+        unsafe { self.copy_out(len) }
+    }
+
+    /// Bitwise copy the first `len` values into a new vector of the exact
+    /// size, one segment at a time.
+    ///
+    /// # Safety
+    ///
+    /// Unless `T` is `Copy`, the caller must ensure the values in the slots
+    /// are not used (or dropped) again.
+    unsafe fn copy_out(&self, len: usize) -> Vec<T> {
+        let mut vec: Vec<T> = Vec::with_capacity(len);
+        let mut copied = 0;
+        for slice in self.slices(0, len) {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    slice.as_ptr(),
+                    vec.as_mut_ptr().add(copied),
+                    slice.len(),
+                );
+            }
+            copied += slice.len();
+        }
+        unsafe { vec.set_len(copied) };
+        vec
+    }
+
+    /// Drops the values in the given range of slots, which must lie within a
+    /// single segment and extend to the last value in that segment. The slots
+    /// are considered to no longer hold values, even if a destructor panics.
+    pub(crate) fn drop_slice(&mut self, start: usize, end: usize) {
+        let ptr = self.slot_ptr(start);
+        unsafe { std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(ptr, end - start)) };
+    }
+}
+
+impl Segments<u8> {
+    /// Offers the `len` slots starting at the given offset, which must be the
+    /// first that does not hold a value, to the reader. Returns the number of
+    /// bytes read, which now hold values.
+    ///
+    /// Readers may only be given initialized memory; `zeroed` is the end of
+    /// the slots known to be zero-filled, which is updated such that slots
+    /// are not zeroed again on the next call.
+    pub(crate) fn read_spare<R: Read + ?Sized>(
+        &mut self,
+        index: usize,
+        len: usize,
+        zeroed: &mut usize,
+        reader: &mut R,
+    ) -> io::Result<usize> {
+        let ptr = self.slot_ptr(index);
+        let start = (*zeroed).max(index);
+        unsafe {
+            unpoison_slots(ptr, len);
+            if start < index + len {
+                ptr.add(start - index).write_bytes(0, index + len - start);
+            }
+        }
+        let buf = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
+        let result = reader.read(buf).map(|read| read.min(len));
+        let read = *result.as_ref().unwrap_or(&0);
+        // the unread portion is once again spare capacity, which is no longer
+        // zero-filled if it has been poisoned
+        unsafe { poison_slots(ptr.add(read), len - read, UNINIT_POISON) };
+        *zeroed = if cfg!(feature = "poison") {
+            index + read
+        } else {
+            start.max(index + len)
+        };
+        result
+    }
+}
@@ -0,0 +1,330 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Descriptive statistics for segment arrays of numeric values, computed in a
+//! single pass over the segments, along with a streaming quantile estimator
+//! that can be updated as values are appended to an array.
+
+use super::{SegmentArray, SegmentStorage};
+
+///
+/// Numeric types that can be summarized by converting each value to `f64`.
+///
+pub trait Numeric: Copy {
+    /// Convert the value to a double-precision float, possibly losing
+    /// precision for large 64-bit and 128-bit integers.
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! impl_numeric {
+    ($($t:ty),*) => {
+        $(impl Numeric for $t {
+            #[inline]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        })*
+    };
+}
+
+impl_numeric!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+///
+/// Summary statistics of the values in an array, as returned by
+/// [`SegmentArray::summary`].
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    /// number of values
+    pub count: usize,
+    /// arithmetic mean of the values
+    pub mean: f64,
+    /// smallest value
+    pub min: f64,
+    /// largest value
+    pub max: f64,
+    // sum of the squared differences from the mean
+    m2: f64,
+}
+
+impl Summary {
+    /// Returns the population variance of the values.
+    pub fn variance(&self) -> f64 {
+        self.m2 / self.count as f64
+    }
+
+    /// Returns the sample variance of the values, or `None` if there are
+    /// fewer than two values.
+    pub fn sample_variance(&self) -> Option<f64> {
+        if self.count < 2 {
+            None
+        } else {
+            Some(self.m2 / (self.count - 1) as f64)
+        }
+    }
+
+    /// Returns the population standard deviation of the values.
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+}
+
+impl<T: Numeric, S: SegmentStorage> SegmentArray<T, S> {
+    /// Computes the mean, variance, minimum, and maximum of the values in a
+    /// single pass over the segments, or returns `None` if the array is
+    /// empty.
+    ///
+    /// Uses Welford's algorithm to avoid the loss of precision that occurs
+    /// when summing the squares of many values.
+    ///
+    /// # Time complexity
+    ///
+    /// O(n) where n is the number of elements in the array.
+    pub fn summary(&self) -> Option<Summary> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut summary = Summary {
+            count: 0,
+            mean: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            m2: 0.0,
+        };
+        for slice in self.range_slices(0, self.len()) {
+            for value in slice {
+                let value = value.to_f64();
+                summary.count += 1;
+                let delta = value - summary.mean;
+                summary.mean += delta / summary.count as f64;
+                summary.m2 += delta * (value - summary.mean);
+                summary.min = summary.min.min(value);
+                summary.max = summary.max.max(value);
+            }
+        }
+        Some(summary)
+    }
+}
+
+///
+/// Streaming estimator of a single quantile using the P² algorithm of Jain
+/// and Chlamtac, which uses constant space regardless of the number of
+/// values observed.
+///
+/// To maintain an estimate as values are appended to an array, observe each
+/// value as it is pushed:
+///
+/// ```
+/// # use segment_array::SegmentArray;
+/// # use segment_array::stats::QuantileSketch;
+/// let mut latencies: SegmentArray<u32> = SegmentArray::new();
+/// let mut p99 = QuantileSketch::new(0.99);
+/// for value in 0..1000 {
+///     latencies.push(value);
+///     p99.observe(value);
+/// }
+/// assert!(p99.estimate().unwrap() > 950.0);
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct QuantileSketch {
+    // quantile being estimated, between 0 and 1
+    quantile: f64,
+    // number of values observed
+    count: usize,
+    // marker heights, the first five values are held here until sorted
+    heights: [f64; 5],
+    // actual marker positions (1-based)
+    positions: [f64; 5],
+    // desired marker positions
+    desired: [f64; 5],
+    // increments to the desired positions for each value
+    increments: [f64; 5],
+}
+
+impl QuantileSketch {
+    /// Return an estimator for the given quantile (e.g. 0.5 for the median).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the quantile is not between 0 and 1 (inclusive).
+    pub fn new(quantile: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&quantile),
+            "quantile must be between 0 and 1"
+        );
+        let p = quantile;
+        Self {
+            quantile,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    /// Returns the number of values observed.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Update the estimate with the given value.
+    pub fn observe<T: Numeric>(&mut self, value: T) {
+        let x = value.to_f64();
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        // find the cell containing the value, adjusting the extremes
+        let h = &mut self.heights;
+        let cell = if x < h[0] {
+            h[0] = x;
+            0
+        } else if x >= h[4] {
+            h[4] = x;
+            3
+        } else {
+            (0..4).find(|i| x < h[i + 1]).unwrap_or(3)
+        };
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        // adjust the heights of the middle markers if necessary
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            let n = &self.positions;
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let height = self.parabolic(i, d);
+                self.heights[i] = if self.heights[i - 1] < height && height < self.heights[i + 1] {
+                    height
+                } else {
+                    self.linear(i, d)
+                };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    /// Returns the estimated value of the quantile, or `None` if no values
+    /// have been observed.
+    pub fn estimate(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1..=5 => {
+                let mut values = self.heights;
+                let values = &mut values[..self.count];
+                values.sort_by(f64::total_cmp);
+                let index = (self.quantile * (self.count - 1) as f64).round() as usize;
+                Some(values[index])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+
+    // Piecewise-parabolic prediction of the height of marker i.
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let h = &self.heights;
+        let n = &self.positions;
+        h[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (h[i + 1] - h[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (h[i] - h[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    // Linear prediction of the height of marker i.
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let h = &self.heights;
+        let n = &self.positions;
+        let j = if d > 0.0 { i + 1 } else { i - 1 };
+        h[i] + d * (h[j] - h[i]) / (n[j] - n[i])
+    }
+}
+
+impl<T: Numeric> Extend<T> for QuantileSketch {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.observe(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_empty() {
+        let sut: SegmentArray<f64> = SegmentArray::new();
+        assert!(sut.summary().is_none());
+    }
+
+    #[test]
+    fn test_summary_ints() {
+        let sut: SegmentArray<i32> = (1..=1000).collect();
+        let summary = sut.summary().unwrap();
+        assert_eq!(summary.count, 1000);
+        assert!((summary.mean - 500.5).abs() < 1e-9);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.max, 1000.0);
+        // variance of 1..=n is (n^2 - 1) / 12
+        assert!((summary.variance() - 83333.25).abs() < 1e-6);
+        assert!((summary.std_dev() - 83333.25f64.sqrt()).abs() < 1e-9);
+        let sample = summary.sample_variance().unwrap();
+        assert!((sample - 83416.6666666).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_summary_single() {
+        let mut sut: SegmentArray<f32> = SegmentArray::new();
+        sut.push(2.5);
+        let summary = sut.summary().unwrap();
+        assert_eq!(summary.mean, 2.5);
+        assert_eq!(summary.variance(), 0.0);
+        assert!(summary.sample_variance().is_none());
+    }
+
+    #[test]
+    fn test_quantile_few_values() {
+        let mut sut = QuantileSketch::new(0.5);
+        assert!(sut.estimate().is_none());
+        sut.extend([5, 1, 3]);
+        assert_eq!(sut.count(), 3);
+        assert_eq!(sut.estimate(), Some(3.0));
+    }
+
+    #[test]
+    fn test_quantile_estimates() {
+        // visit 0..100_000 in a scrambled order
+        let values: SegmentArray<u64> = (0..100_000u64).map(|v| (v * 7919) % 100_000).collect();
+        for quantile in [0.1, 0.5, 0.9, 0.99] {
+            let mut sut = QuantileSketch::new(quantile);
+            sut.extend(values.iter().copied());
+            let expected = quantile * 100_000.0;
+            let actual = sut.estimate().unwrap();
+            assert!(
+                (actual - expected).abs() < 1000.0,
+                "quantile {quantile} estimate {actual}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "quantile must be between 0 and 1")]
+    fn test_quantile_invalid() {
+        QuantileSketch::new(1.5);
+    }
+}
@@ -0,0 +1,598 @@
+//
+// Copyright (c) 2025 Nathan Fiedler
+//
+
+//! Backends that provide the memory for the segments of an array, including
+//! the global heap (the default), a fixed-size buffer that may be placed in a
+//! static, and (with the `mmap` feature) anonymous memory mappings, as well as
+//! a pool that recycles segments among arrays.
+//!
+//! Other kinds of memory, such as pinned host memory for a GPU, can be used
+//! by implementing the [`SegmentStorage`] trait and creating arrays with
+//! [`SegmentArray::new_in`](crate::SegmentArray::new_in).
+
+use std::alloc::{Layout, alloc, alloc_zeroed, dealloc, handle_alloc_error};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+///
+/// Source of the memory for the segments of an array.
+///
+/// The storage is cloned when a new array is derived from an existing one,
+/// such as with `clone()`, and hence should be a cheap handle.
+///
+/// # Safety
+///
+/// Implementations must return either a null pointer or a pointer to a block
+/// of memory that fits the given layout, and which remains valid, and is not
+/// handed out again, until it is passed to `deallocate()` on this storage or
+/// any of its clones.
+///
+/// Each array deallocates its segments in the reverse order in which they
+/// were allocated, which implementations are free to rely upon.
+///
+pub unsafe trait SegmentStorage: Clone {
+    /// Allocate a block of memory for the given layout, returning null if the
+    /// memory could not be allocated.
+    fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// Allocate a block of memory that is filled with zeros, returning null
+    /// if the memory could not be allocated.
+    fn allocate_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.allocate(layout);
+        if !ptr.is_null() {
+            unsafe { ptr.write_bytes(0, layout.size()) };
+        }
+        ptr
+    }
+
+    /// Release a block of memory.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been returned by `allocate()` or
+    /// `allocate_zeroed()` from this storage (or a clone), with the same
+    /// layout, and not already deallocated.
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+
+    /// Invoked when an allocation fails in an operation that cannot return an
+    /// error, such as `push()`. The default implementation calls
+    /// `std::alloc::handle_alloc_error()`, which typically aborts.
+    fn allocation_failed(&self, layout: Layout) -> ! {
+        handle_alloc_error(layout)
+    }
+}
+
+///
+/// Storage that allocates segments from the global allocator.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Heap;
+
+unsafe impl SegmentStorage for Heap {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc(layout) }
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc_zeroed(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { dealloc(ptr, layout) }
+    }
+}
+
+///
+/// Fixed-size buffer from which segments are allocated in the manner of a
+/// stack, suitable for placing in a static to avoid the use of the heap.
+///
+/// Space is reclaimed only when the most recently allocated block is
+/// deallocated, which is always the case when a single array uses the buffer.
+///
+/// ```
+/// # use segment_array::SegmentArray;
+/// # use segment_array::storage::StaticBuffer;
+/// static BUFFER: StaticBuffer<4096> = StaticBuffer::new();
+/// let mut arr = SegmentArray::new_in(&BUFFER);
+/// for value in 0..192u64 {
+///     arr.push(value);
+/// }
+/// assert_eq!(BUFFER.used(), 192 * 8);
+/// ```
+///
+// aligned to a cache line such that typical elements need no padding
+#[repr(C, align(64))]
+pub struct StaticBuffer<const N: usize> {
+    memory: UnsafeCell<[MaybeUninit<u8>; N]>,
+    // offset of the first byte that has not been allocated
+    top: AtomicUsize,
+}
+
+// blocks are handed out exclusively by means of the atomic offset
+unsafe impl<const N: usize> Sync for StaticBuffer<N> {}
+
+impl<const N: usize> StaticBuffer<N> {
+    /// Return a buffer of `N` bytes from which nothing has been allocated.
+    pub const fn new() -> Self {
+        Self {
+            memory: UnsafeCell::new([MaybeUninit::uninit(); N]),
+            top: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of bytes that have been allocated from the buffer,
+    /// including any padding needed for alignment.
+    pub fn used(&self) -> usize {
+        self.top.load(Ordering::Acquire)
+    }
+}
+
+impl<const N: usize> Default for StaticBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const N: usize> SegmentStorage for &StaticBuffer<N> {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        let base = self.memory.get().cast::<u8>();
+        let mut top = self.top.load(Ordering::Acquire);
+        loop {
+            let padding = base.wrapping_add(top).align_offset(layout.align());
+            let start = top.saturating_add(padding);
+            let end = start.saturating_add(layout.size());
+            if end > N {
+                return std::ptr::null_mut();
+            }
+            match self
+                .top
+                .compare_exchange_weak(top, end, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return unsafe { base.add(start) },
+                Err(current) => top = current,
+            }
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        let start = ptr as usize - self.memory.get() as usize;
+        // only the most recent block can be reclaimed, otherwise the space
+        // remains in use
+        let _ = self.top.compare_exchange(
+            start + layout.size(),
+            start,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+    }
+}
+
+///
+/// Storage that allocates each segment as an anonymous, private memory
+/// mapping, such that the memory is returned to the operating system as soon
+/// as the segment is deallocated.
+///
+#[cfg(all(feature = "mmap", unix))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Mmap;
+
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl SegmentStorage for Mmap {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        // mappings are aligned to the page size and no more
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        if layout.align() > page_size {
+            return std::ptr::null_mut();
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                layout.size(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            std::ptr::null_mut()
+        } else {
+            ptr.cast()
+        }
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> *mut u8 {
+        // anonymous mappings are always zero-filled
+        self.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { libc::munmap(ptr.cast(), layout.size()) };
+    }
+}
+
+///
+/// Storage that keeps deallocated segments in a cache, keyed by their layout,
+/// and hands them out again for later allocations by any array sharing the
+/// pool, rather than returning them to the underlying storage. This avoids the
+/// cost of the allocator when many large arrays are created and dropped.
+///
+/// The total size of the cached segments is limited to the number of bytes
+/// given when the pool is created; any segments beyond that are deallocated.
+/// The cached segments are released when the last handle to the pool is
+/// dropped, or by calling [`Self::release`].
+///
+/// ```
+/// # use segment_array::SegmentArray;
+/// # use segment_array::storage::SegmentPool;
+/// let pool = SegmentPool::new(1 << 20);
+/// let mut first: SegmentArray<u64, _> = SegmentArray::new_in(pool.clone());
+/// first.extend_from_slice_copy(&[1; 100]);
+/// drop(first);
+/// assert_eq!(pool.cached_bytes(), 192 * 8);
+/// // the segments of the first array are reused by the second
+/// let mut second: SegmentArray<u64, _> = SegmentArray::new_in(pool.clone());
+/// second.extend_from_slice_copy(&[2; 100]);
+/// assert_eq!(pool.cached_bytes(), 0);
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct SegmentPool<S: SegmentStorage = Heap>(Arc<PoolState<S>>);
+
+#[derive(Debug)]
+struct PoolState<S: SegmentStorage> {
+    inner: S,
+    // maximum number of bytes in cached segments
+    limit: usize,
+    // segments available for reuse, and their total size in bytes
+    cache: Mutex<(HashMap<Layout, Vec<CachedSegment>>, usize)>,
+}
+
+// Segment that has been deallocated by an array and is not in use.
+#[derive(Debug)]
+struct CachedSegment(*mut u8);
+
+// the memory of a cached segment belongs to the pool alone
+unsafe impl Send for CachedSegment {}
+
+impl SegmentPool {
+    /// Return a pool that allocates from the heap, caching at most the given
+    /// number of bytes of deallocated segments.
+    pub fn new(limit: usize) -> Self {
+        Self::wrap(Heap, limit)
+    }
+}
+
+impl<S: SegmentStorage> SegmentPool<S> {
+    /// Return a pool that allocates from the given storage, caching at most
+    /// the given number of bytes of deallocated segments.
+    pub fn wrap(inner: S, limit: usize) -> Self {
+        Self(Arc::new(PoolState {
+            inner,
+            limit,
+            cache: Mutex::new((HashMap::new(), 0)),
+        }))
+    }
+
+    /// Returns the total size in bytes of the segments held for reuse.
+    pub fn cached_bytes(&self) -> usize {
+        self.0.cache().1
+    }
+
+    /// Returns the cached segments to the underlying storage.
+    pub fn release(&self) {
+        self.0.release();
+    }
+}
+
+impl<S: SegmentStorage> PoolState<S> {
+    fn cache(&self) -> MutexGuard<'_, (HashMap<Layout, Vec<CachedSegment>>, usize)> {
+        // the cache is consistent even if a thread panicked while holding it
+        self.cache.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn take(&self, layout: Layout) -> Option<*mut u8> {
+        let mut cache = self.cache();
+        let segment = cache.0.get_mut(&layout)?.pop()?;
+        cache.1 -= layout.size();
+        Some(segment.0)
+    }
+
+    fn release(&self) {
+        let segments = {
+            let mut cache = self.cache();
+            cache.1 = 0;
+            std::mem::take(&mut cache.0)
+        };
+        for (layout, blocks) in segments {
+            for segment in blocks {
+                unsafe { self.inner.deallocate(segment.0, layout) };
+            }
+        }
+    }
+}
+
+impl<S: SegmentStorage> Drop for PoolState<S> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+unsafe impl<S: SegmentStorage> SegmentStorage for SegmentPool<S> {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        match self.0.take(layout) {
+            Some(ptr) => ptr,
+            None => self.0.inner.allocate(layout),
+        }
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> *mut u8 {
+        match self.0.take(layout) {
+            Some(ptr) => {
+                unsafe { ptr.write_bytes(0, layout.size()) };
+                ptr
+            }
+            None => self.0.inner.allocate_zeroed(layout),
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        {
+            let mut cache = self.0.cache();
+            if cache.1 + layout.size() <= self.0.limit {
+                cache.0.entry(layout).or_default().push(CachedSegment(ptr));
+                cache.1 += layout.size();
+                return;
+            }
+        }
+        unsafe { self.0.inner.deallocate(ptr, layout) }
+    }
+
+    fn allocation_failed(&self, layout: Layout) -> ! {
+        self.0.inner.allocation_failed(layout)
+    }
+}
+
+///
+/// Storage for testing that fails the Nth allocation (counting from 1) made by
+/// any of its clones, and which panics rather than aborting when an operation
+/// cannot return the error, allowing error handling and panic safety to be
+/// exercised.
+///
+/// ```
+/// # use segment_array::{FailingStorage, SegmentArray};
+/// let mut arr: SegmentArray<u32, _> = SegmentArray::new_in(FailingStorage::new(2));
+/// assert!(arr.try_reserve(64).is_ok());
+/// assert!(arr.try_reserve(65).is_err());
+/// ```
+///
+#[cfg(feature = "test-support")]
+#[derive(Clone, Debug)]
+pub struct FailingStorage<S: SegmentStorage = Heap> {
+    inner: S,
+    fail_at: usize,
+    // number of allocations attempted, shared by all clones
+    attempts: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "test-support")]
+impl FailingStorage {
+    /// Return a storage that allocates from the heap, failing the allocation
+    /// with the given number.
+    pub fn new(fail_at: usize) -> Self {
+        Self::wrap(Heap, fail_at)
+    }
+}
+
+#[cfg(feature = "test-support")]
+impl<S: SegmentStorage> FailingStorage<S> {
+    /// Return a storage that allocates from the given storage, failing the
+    /// allocation with the given number.
+    pub fn wrap(inner: S, fail_at: usize) -> Self {
+        Self {
+            inner,
+            fail_at,
+            attempts: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the number of allocations that have been attempted.
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    fn should_fail(&self) -> bool {
+        self.attempts.fetch_add(1, Ordering::Relaxed) + 1 == self.fail_at
+    }
+}
+
+#[cfg(feature = "test-support")]
+unsafe impl<S: SegmentStorage> SegmentStorage for FailingStorage<S> {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        if self.should_fail() {
+            std::ptr::null_mut()
+        } else {
+            self.inner.allocate(layout)
+        }
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> *mut u8 {
+        if self.should_fail() {
+            std::ptr::null_mut()
+        } else {
+            self.inner.allocate_zeroed(layout)
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.deallocate(ptr, layout) }
+    }
+
+    fn allocation_failed(&self, layout: Layout) -> ! {
+        panic!("memory allocation of {} bytes failed", layout.size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentArray;
+
+    #[test]
+    fn test_static_buffer_stack_order() {
+        let buffer: StaticBuffer<1024> = StaticBuffer::new();
+        let storage = &buffer;
+        let small = Layout::from_size_align(10, 1).unwrap();
+        let aligned = Layout::from_size_align(64, 16).unwrap();
+        let first = storage.allocate(small);
+        let second = storage.allocate_zeroed(aligned);
+        assert!(!first.is_null() && !second.is_null());
+        assert_eq!(second as usize % 16, 0);
+        assert!(
+            unsafe { std::slice::from_raw_parts(second, 64) }
+                .iter()
+                .all(|b| *b == 0)
+        );
+        let used = buffer.used();
+        assert!(used >= 74);
+        // freeing out of order does not reclaim the space
+        unsafe { storage.deallocate(first, small) };
+        assert_eq!(buffer.used(), used);
+        unsafe { storage.deallocate(second, aligned) };
+        assert!(buffer.used() < used);
+        let large = Layout::from_size_align(2048, 1).unwrap();
+        assert!(storage.allocate(large).is_null());
+    }
+
+    #[test]
+    fn test_static_buffer_array() {
+        static BUFFER: StaticBuffer<{ 512 * 4 }> = StaticBuffer::new();
+        let mut sut: SegmentArray<u32, _> = SegmentArray::new_in(&BUFFER);
+        for value in 0..448 {
+            sut.push(value);
+        }
+        assert_eq!(BUFFER.used(), 448 * 4);
+        let copy = sut.clone_range(..10);
+        assert_eq!(copy.len(), 10);
+        drop(copy);
+        for value in (0..448).rev() {
+            assert_eq!(sut.pop(), Some(value));
+        }
+        assert_eq!(BUFFER.used(), 0);
+        let zeroed: SegmentArray<u32, _> = SegmentArray::with_zeroed_in(300, &BUFFER);
+        assert!(zeroed.iter().all(|v| *v == 0));
+    }
+
+    #[test]
+    fn test_static_buffer_new_in_static() {
+        static BUFFER: StaticBuffer<{ 64 * 8 }> = StaticBuffer::new();
+        static ARRAY: std::sync::Mutex<SegmentArray<u64, &StaticBuffer<{ 64 * 8 }>>> =
+            std::sync::Mutex::new(SegmentArray::new_in(&BUFFER));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    for value in 0..16 {
+                        ARRAY.lock().unwrap().push(t * 16 + value);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut array = ARRAY.lock().unwrap();
+        assert_eq!(array.len(), 64);
+        assert_eq!(BUFFER.used(), 64 * 8);
+        // the buffer is full so the next segment cannot be allocated
+        assert!(array.try_push(64).is_err());
+        array.clear();
+        assert_eq!(BUFFER.used(), 0);
+    }
+
+    // Storage that tracks the number of bytes currently allocated.
+    #[derive(Clone, Default)]
+    struct Counting(std::sync::Arc<AtomicUsize>);
+
+    unsafe impl SegmentStorage for Counting {
+        fn allocate(&self, layout: Layout) -> *mut u8 {
+            self.0.fetch_add(layout.size(), Ordering::Relaxed);
+            Heap.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+            self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+            unsafe { Heap.deallocate(ptr, layout) }
+        }
+    }
+
+    #[test]
+    fn test_custom_storage() {
+        let storage = Counting::default();
+        let mut sut: SegmentArray<u64, Counting> = SegmentArray::new_in(storage.clone());
+        sut.extend_from_slice_copy(&[1; 100]);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 192 * 8);
+        // clones allocate from the same storage
+        let copy = sut.clone();
+        assert_eq!(storage.0.load(Ordering::Relaxed), 2 * 192 * 8);
+        drop(sut);
+        let zeroed: SegmentArray<u64, _> = SegmentArray::with_zeroed_in(10, storage.clone());
+        assert!(zeroed.iter().all(|v| *v == 0));
+        drop(zeroed);
+        assert_eq!(copy.into_iter().sum::<u64>(), 100);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_segment_pool() {
+        let storage = Counting::default();
+        let pool = SegmentPool::wrap(storage.clone(), 448 * 8);
+        let mut sut: SegmentArray<u64, _> = SegmentArray::new_in(pool.clone());
+        sut.extend_from_slice_copy(&[1; 1000]);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 1984 * 8);
+        drop(sut);
+        // the largest segments do not fit within the limit
+        assert_eq!(pool.cached_bytes(), 448 * 8);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 448 * 8);
+        let mut sut: SegmentArray<u64, _> = SegmentArray::new_in(pool.clone());
+        sut.extend_from_slice_copy(&[2; 100]);
+        assert_eq!(pool.cached_bytes(), 256 * 8);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 448 * 8);
+        // segments taken from the cache are zeroed when required
+        let zeroed: SegmentArray<u64, _> = SegmentArray::with_zeroed_in(400, pool.clone());
+        assert!(zeroed.iter().all(|v| *v == 0));
+        assert_eq!(storage.0.load(Ordering::Relaxed), 640 * 8);
+        // segments of other layouts are not reused
+        let mut bytes: SegmentArray<u8, _> = SegmentArray::new_in(pool.clone());
+        bytes.push(1);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 640 * 8 + 64);
+        drop(bytes);
+        pool.release();
+        assert_eq!(pool.cached_bytes(), 0);
+        drop(zeroed);
+        drop(pool);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 192 * 8 + 448 * 8);
+        // the cache is released along with the last array using the pool
+        drop(sut);
+        assert_eq!(storage.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(all(feature = "mmap", unix))]
+    #[test]
+    fn test_mmap_array() {
+        let mut sut: SegmentArray<String, Mmap> = SegmentArray::new_in(Mmap);
+        for value in 0..5000 {
+            sut.push(value.to_string());
+        }
+        for (idx, value) in sut.iter().enumerate() {
+            assert_eq!(*value, idx.to_string());
+        }
+        let zeroed: SegmentArray<u64, _> = SegmentArray::with_zeroed_in(5000, Mmap);
+        assert!(zeroed.iter().all(|v| *v == 0));
+        sut.clear();
+        assert_eq!(sut.capacity(), 0);
+    }
+}